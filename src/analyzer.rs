@@ -1,128 +1,457 @@
+use crate::ast::visit::{walk_children, walk_children_mut, Visitor, VisitorMut};
 use crate::ast::{Attr, ExpressionKind, ExpressionType, Kind, StatementKind, TreeNode};
+use crate::diagnostic::Diagnostic;
+use crate::liveness;
 use crate::symtable::SymTable;
 use crate::token::Token;
 use anyhow::Result;
-
-type InsertEmptyCallback = fn(&Option<Box<TreeNode>>);
-type InsertCallback = fn(&mut Analyzer, &mut SymTable, &Option<Box<TreeNode>>);
-
-type TypeEmptyCallback = fn(&mut Option<Box<TreeNode>>) -> Result<()>;
-type TypeCheckCallback = fn(&mut Option<Box<TreeNode>>) -> Result<()>;
+use std::collections::{BTreeMap, BTreeSet};
 
 #[derive(Debug, Clone, Default)]
 pub struct Analyzer {
     location: i32,
 }
 
-fn insert_empty(_node: &Option<Box<TreeNode>>) {}
-
-fn insert_node(analyzer: &mut Analyzer, sym_table: &mut SymTable, node: &Option<Box<TreeNode>>) {
-    if let Some(node) = node {
-        match &node.kind {
-            Kind::Statement(stmt) => match stmt {
-                StatementKind::AssignK | StatementKind::ReadK => {
-                    if let Attr::Name(str) = &node.attr {
-                        if sym_table.st_lookup(str.as_str()).is_none() {
-                            sym_table.st_insert(
-                                str.as_str(),
-                                node.line_number,
-                                analyzer.add_location(),
-                            );
-                        } else {
-                            sym_table.st_insert(str.as_str(), node.line_number, 0);
-                        }
-                    }
-                }
-                _ => {}
-            },
-            Kind::Expression(expr) => {
-                if expr == &ExpressionKind::IdK {
-                    if let Attr::Name(str) = &node.attr {
-                        if sym_table.st_lookup(str.as_str()).is_none() {
-                            sym_table.st_insert(
-                                str.as_str(),
-                                node.line_number,
-                                analyzer.add_location(),
-                            );
-                        } else {
-                            sym_table.st_insert(str.as_str(), node.line_number, 0);
-                        }
-                    }
-                }
+// records every variable with the lines it appears on, in preorder so that
+// memory locations are handed out in order of first appearance
+struct SymbolTableBuilder<'a> {
+    analyzer: &'a mut Analyzer,
+    sym_table: SymTable,
+}
+
+impl SymbolTableBuilder<'_> {
+    fn insert(&mut self, node: &TreeNode) {
+        if let Attr::Name(str) = &node.attr {
+            if self.sym_table.st_lookup(str.as_str()).is_none() {
+                let location = self.analyzer.add_location();
+                self.sym_table
+                    .st_insert(str.as_str(), node.line_number, location);
+            } else {
+                self.sym_table.st_insert(str.as_str(), node.line_number, 0);
+            }
+            if node.kind == Kind::Expression(ExpressionKind::IdK) {
+                self.sym_table.st_use(str, node.line_number);
+            } else {
+                self.sym_table.st_def(str, node.line_number);
             }
         }
     }
 }
 
-fn type_empty(_node: &mut Option<Box<TreeNode>>) -> Result<()> {
-    Ok(())
+impl Visitor for SymbolTableBuilder<'_> {
+    fn visit_stmt(&mut self, node: &TreeNode) {
+        if matches!(
+            node.kind,
+            Kind::Statement(StatementKind::AssignK | StatementKind::ReadK)
+        ) {
+            self.insert(node);
+        }
+        walk_children(self, node)
+    }
+
+    fn visit_expr(&mut self, node: &TreeNode) {
+        if node.kind == Kind::Expression(ExpressionKind::IdK) {
+            self.insert(node);
+        }
+        walk_children(self, node)
+    }
+}
+
+// checks each node in postorder, once the types of its children are known,
+// collecting every error rather than stopping at the first
+struct TypeChecker {
+    vars: BTreeMap<String, ExpressionType>,
+    errors: Vec<Diagnostic>,
 }
-fn type_node(node: &mut Option<Box<TreeNode>>) -> Result<()> {
-    if let Some(node) = node {
-        match &node.kind {
-            Kind::Statement(stmt) => match stmt {
-                StatementKind::IfK | StatementKind::AssignK | StatementKind::WriteK => {
-                    if node.child[0].is_none() {
-                        return Err(anyhow::format_err!("1 {:#?} {}", stmt, node));
-                    }
-                    if let Some(node2) = &node.child[0] {
-                        match stmt {
-                            StatementKind::IfK => {
-                                if node2.expression_type == ExpressionType::Integer {
-                                    return Err(anyhow::format_err!("2 {:#?} {:#?}", stmt, node));
-                                }
-                            }
-                            StatementKind::AssignK | StatementKind::WriteK => {
-                                if node2.expression_type != ExpressionType::Integer {
-                                    return Err(anyhow::format_err!("2 {:#?} {:#?}", stmt, node));
-                                }
-                            }
-                            _ => {}
+
+impl VisitorMut for TypeChecker {
+    fn visit_node_mut(&mut self, node: &mut TreeNode) {
+        walk_children_mut(self, node);
+        type_node(node, &self.vars, &mut self.errors);
+    }
+}
+
+// Infers the type of each variable from the first statement in the source
+// that gives it a value: `read` makes it an integer, an assignment gives it
+// the type of the assigned value. An assignment from a variable whose own
+// type is not known yet is settled on a later pass. Variables whose type
+// cannot be told this way are integers.
+#[derive(Default)]
+struct TypeInference {
+    // the value of each variable's first assignment, `None` for `read`
+    first: BTreeMap<String, Option<TreeNode>>,
+    vars: BTreeMap<String, ExpressionType>,
+}
+
+impl TypeInference {
+    fn infer(tree: Option<&TreeNode>) -> BTreeMap<String, ExpressionType> {
+        let mut inference = Self::default();
+        inference.visit_sequence(tree);
+        loop {
+            let settled: Vec<(String, ExpressionType)> = inference
+                .first
+                .iter()
+                .filter(|(name, _)| !inference.vars.contains_key(*name))
+                .filter_map(|(name, value)| {
+                    let ty = match value {
+                        Some(value) => inference.type_of(value)?,
+                        None => ExpressionType::Integer,
+                    };
+                    Some((name.clone(), ty))
+                })
+                .collect();
+            if settled.is_empty() {
+                return inference.vars;
+            }
+            inference.vars.extend(settled);
+        }
+    }
+
+    // the type of the expression `node`, if the types of its variables are known
+    fn type_of(&self, node: &TreeNode) -> Option<ExpressionType> {
+        match (&node.kind, &node.attr) {
+            (Kind::Expression(ExpressionKind::ConstK), Attr::Bool(_)) => {
+                Some(ExpressionType::Boolean)
+            }
+            (Kind::Expression(ExpressionKind::ConstK), _) => Some(ExpressionType::Integer),
+            (Kind::Expression(ExpressionKind::IdK), Attr::Name(name)) => {
+                self.vars.get(name).cloned()
+            }
+            (Kind::Expression(ExpressionKind::Opk), Attr::Op(op)) => Some(operator_types(op).1),
+            _ => None,
+        }
+    }
+}
+
+impl Visitor for TypeInference {
+    fn visit_stmt(&mut self, node: &TreeNode) {
+        if let (Kind::Statement(StatementKind::AssignK | StatementKind::ReadK), Attr::Name(name)) =
+            (&node.kind, &node.attr)
+        {
+            let value = node.child[0].as_deref().cloned();
+            self.first.entry(name.clone()).or_insert(value);
+        }
+        walk_children(self, node)
+    }
+}
+
+fn var_type(vars: &BTreeMap<String, ExpressionType>, attr: &Attr) -> ExpressionType {
+    match attr {
+        Attr::Name(name) => vars.get(name).cloned(),
+        _ => None,
+    }
+    .unwrap_or(ExpressionType::Integer)
+}
+
+// Warns about variables that may be read before they are assigned. `assigned`
+// holds the variables assigned on every path to the current node: an `if`
+// keeps those assigned in both branches, and a `repeat` body always runs at
+// least once, so its assignments count after the loop. A `while` body may
+// not run at all, so its assignments are forgotten after the loop; the same
+// goes for a `for` body, though the loop variable itself is always assigned.
+#[derive(Default)]
+struct DefiniteAssignment {
+    assigned: BTreeSet<String>,
+    warnings: Vec<Diagnostic>,
+}
+
+impl DefiniteAssignment {
+    fn assign(&mut self, node: &TreeNode) {
+        if let Attr::Name(name) = &node.attr {
+            self.assigned.insert(name.clone());
+        }
+    }
+
+    fn child(&mut self, node: &TreeNode, i: usize) {
+        self.visit_sequence(node.child[i].as_deref())
+    }
+}
+
+impl Visitor for DefiniteAssignment {
+    fn visit_stmt(&mut self, node: &TreeNode) {
+        match node.kind {
+            Kind::Statement(StatementKind::IfK) => {
+                self.child(node, 0);
+                let before = self.assigned.clone();
+                self.child(node, 1);
+                let then_assigned = std::mem::replace(&mut self.assigned, before);
+                self.child(node, 2);
+                self.assigned = self
+                    .assigned
+                    .intersection(&then_assigned)
+                    .cloned()
+                    .collect();
+            }
+            Kind::Statement(StatementKind::WhileK) => {
+                self.child(node, 0);
+                let before = self.assigned.clone();
+                self.child(node, 1);
+                self.assigned = before;
+            }
+            Kind::Statement(StatementKind::ForK) => {
+                self.child(node, 0);
+                self.child(node, 1);
+                self.child(node, 2);
+                let before = self.assigned.clone();
+                self.child(node, 3);
+                self.assigned = before;
+            }
+            Kind::Statement(StatementKind::AssignK) => {
+                walk_children(self, node);
+                self.assign(node);
+            }
+            Kind::Statement(StatementKind::ReadK) => self.assign(node),
+            _ => walk_children(self, node),
+        }
+    }
+
+    fn visit_expr(&mut self, node: &TreeNode) {
+        if let (Kind::Expression(ExpressionKind::IdK), Attr::Name(name)) = (&node.kind, &node.attr)
+        {
+            if !self.assigned.contains(name) {
+                let message = format!("`{}` may be used before it is assigned", name);
+                self.warnings.push(
+                    Diagnostic::warning("W0001", message, node.span)
+                        .with_label("read here")
+                        .with_note("variables must be assigned with `:=` or `read` first"),
+                );
+                // warn once per path, not at every later use
+                self.assigned.insert(name.clone());
+            }
+        }
+        walk_children(self, node)
+    }
+}
+
+fn type_node(
+    node: &mut TreeNode,
+    vars: &BTreeMap<String, ExpressionType>,
+    errors: &mut Vec<Diagnostic>,
+) {
+    match &node.kind {
+        Kind::Statement(stmt) => match stmt {
+            StatementKind::IfK | StatementKind::AssignK | StatementKind::WriteK => {
+                if node.child[0].is_none() {
+                    errors.push(malformed(node));
+                }
+                if let Some(node2) = &node.child[0] {
+                    match stmt {
+                        StatementKind::IfK if node2.expression_type == ExpressionType::Integer => {
+                            let message = format!(
+                                "if-condition must be boolean, found {}",
+                                node2.expression_type
+                            );
+                            errors.push(
+                                mismatch("E0201", message, node2, ExpressionType::Boolean)
+                                    .with_secondary(node.span, "in this if-statement"),
+                            );
+                        }
+                        StatementKind::AssignK
+                            if node2.expression_type != var_type(vars, &node.attr) =>
+                        {
+                            let expected = var_type(vars, &node.attr);
+                            let message = format!(
+                                "cannot assign {} value to {} variable `{}`",
+                                node2.expression_type, expected, node.attr
+                            );
+                            errors.push(mismatch("E0203", message, node2, expected));
+                        }
+                        StatementKind::WriteK
+                            if node2.expression_type != ExpressionType::Integer =>
+                        {
+                            let message = format!(
+                                "write value must be integer, found {}",
+                                node2.expression_type
+                            );
+                            errors.push(mismatch("E0204", message, node2, ExpressionType::Integer));
                         }
+                        _ => {}
                     }
                 }
-                StatementKind::RepeatK => {
-                    if node.child[1].is_none() {
-                        return Err(anyhow::format_err!("{:#?} {}", stmt, node));
+            }
+            StatementKind::ReadK if var_type(vars, &node.attr) != ExpressionType::Integer => {
+                let message = format!(
+                    "cannot read into {} variable `{}`, only integers can be read",
+                    var_type(vars, &node.attr),
+                    node.attr
+                );
+                errors.push(Diagnostic::error("E0208", message, node.span).with_label("read here"));
+            }
+            StatementKind::RepeatK => {
+                if node.child[1].is_none() {
+                    errors.push(malformed(node));
+                }
+                if let Some(node2) = &node.child[1] {
+                    if node2.expression_type != ExpressionType::Boolean {
+                        let message = format!(
+                            "until-condition must be boolean, found {}",
+                            node2.expression_type
+                        );
+                        errors.push(
+                            mismatch("E0202", message, node2, ExpressionType::Boolean)
+                                .with_secondary(node.span, "in this repeat-statement"),
+                        );
                     }
-                    if let Some(node2) = &node.child[1] {
-                        if node2.expression_type != ExpressionType::Boolean {
-                            return Err(anyhow::format_err!("3 {:#?} {}", stmt, node));
-                        }
+                }
+            }
+            StatementKind::WhileK => {
+                if node.child[0].is_none() {
+                    errors.push(malformed(node));
+                }
+                if let Some(node2) = &node.child[0] {
+                    if node2.expression_type != ExpressionType::Boolean {
+                        let message = format!(
+                            "while-condition must be boolean, found {}",
+                            node2.expression_type
+                        );
+                        errors.push(
+                            mismatch("E0206", message, node2, ExpressionType::Boolean)
+                                .with_secondary(node.span, "in this while-statement"),
+                        );
                     }
                 }
-                _ => {}
-            },
-            Kind::Expression(expr) => match expr {
-                ExpressionKind::Opk => {
-                    if node.child[0].is_none() || node.child[1].is_none() {
-                        return Err(anyhow::format_err!("4 {:#?} {}", expr, node));
+            }
+            StatementKind::ForK => {
+                if node.child[0].is_none() || node.child[1].is_none() {
+                    errors.push(malformed(node));
+                }
+                // the initial value is checked against the variable by its
+                // assignment, so only the variable's type is left to check
+                if let Some(init) = &node.child[0] {
+                    let ty = var_type(vars, &init.attr);
+                    if ty != ExpressionType::Integer {
+                        let message = format!(
+                            "for-loop variable `{}` must be integer, found {}",
+                            init.attr, ty
+                        );
+                        let label = format!("expected integer, found {}", ty);
+                        errors.push(
+                            Diagnostic::error("E0207", message, init.span)
+                                .with_label(&label)
+                                .with_secondary(node.span, "in this for-statement"),
+                        );
                     }
-
-                    if let (Some(node2), Some(node3)) = (&node.child[0], &node.child[1]) {
-                        if node2.expression_type != ExpressionType::Integer
-                            || node3.expression_type != ExpressionType::Integer
-                        {
-                            return Err(anyhow::format_err!("5 {:#?} {}", expr, node));
+                }
+                for (part, child) in [("bound", &node.child[1]), ("step", &node.child[2])] {
+                    if let Some(node2) = child {
+                        if node2.expression_type != ExpressionType::Integer {
+                            let message = format!(
+                                "for-loop {} must be integer, found {}",
+                                part, node2.expression_type
+                            );
+                            errors.push(
+                                mismatch("E0207", message, node2, ExpressionType::Integer)
+                                    .with_secondary(node.span, "in this for-statement"),
+                            );
                         }
                     }
-
-                    if let Attr::Op(token) = &node.attr {
-                        match token {
-                            &Token::Eq | &Token::Lt => {
-                                node.expression_type = ExpressionType::Boolean
-                            }
-                            _ => node.expression_type = ExpressionType::Integer,
+                }
+                // the step is added to the counter, or subtracted with
+                // `downto`, so it has to be positive for the loop to move on
+                if let Some(step) = &node.child[2] {
+                    if let Attr::Val(val) = step.attr {
+                        if step.kind == Kind::Expression(ExpressionKind::ConstK) && val <= 0 {
+                            let message = format!("for-loop step must be positive, found {}", val);
+                            errors.push(
+                                Diagnostic::error("E0209", message, step.span)
+                                    .with_label("expected a step greater than 0")
+                                    .with_secondary(node.span, "in this for-statement"),
+                            );
                         }
                     }
                 }
-                ExpressionKind::ConstK | ExpressionKind::IdK => {
-                    node.expression_type = ExpressionType::Integer;
+            }
+            _ => {}
+        },
+        Kind::Expression(expr) => match expr {
+            ExpressionKind::Opk => {
+                let Attr::Op(op) = &node.attr else {
+                    errors.push(malformed(node));
+                    return;
+                };
+                let (mut operand_type, result_type) = operator_types(op);
+                // `=` and `<>` also compare two booleans
+                if matches!(op, Token::Eq | Token::Ne)
+                    && node.child[0].as_ref().map(|lhs| &lhs.expression_type)
+                        == Some(&ExpressionType::Boolean)
+                {
+                    operand_type = ExpressionType::Boolean;
                 }
-            },
-        }
+                if node.child[0].is_none() || (node.is_unary() && !is_unary_op(op)) {
+                    errors.push(malformed(node));
+                }
+
+                for operand in node.child.iter().flatten() {
+                    if operand.expression_type != operand_type {
+                        let message = if node.is_unary() {
+                            format!(
+                                "operand of `{}` must be {}, found {}",
+                                op.text(),
+                                operand_type,
+                                operand.expression_type
+                            )
+                        } else {
+                            format!(
+                                "operands of `{}` must be {}s, found {}",
+                                op.text(),
+                                operand_type,
+                                operand.expression_type
+                            )
+                        };
+                        errors.push(
+                            mismatch("E0205", message, operand, operand_type.clone())
+                                .with_secondary(node.span, "in this expression"),
+                        );
+                    }
+                }
+
+                // the result type follows from the operator even when an
+                // operand is wrong, so one mistake is reported only once
+                node.expression_type = result_type;
+            }
+            ExpressionKind::ConstK => {
+                node.expression_type = match node.attr {
+                    Attr::Bool(_) => ExpressionType::Boolean,
+                    _ => ExpressionType::Integer,
+                };
+            }
+            ExpressionKind::IdK => {
+                node.expression_type = var_type(vars, &node.attr);
+            }
+        },
     }
-    Ok(())
+}
+
+// the type an operator requires of its operands, and the type of its result
+fn operator_types(op: &Token) -> (ExpressionType, ExpressionType) {
+    match op {
+        Token::And | Token::Or | Token::Not => (ExpressionType::Boolean, ExpressionType::Boolean),
+        op if op.is_comparison() => (ExpressionType::Integer, ExpressionType::Boolean),
+        _ => (ExpressionType::Integer, ExpressionType::Integer),
+    }
+}
+
+// operators that may be applied to a single operand
+fn is_unary_op(op: &Token) -> bool {
+    matches!(op, Token::Not | Token::Minus | Token::Plus)
+}
+
+fn mismatch(
+    code: &'static str,
+    message: String,
+    operand: &TreeNode,
+    expected: ExpressionType,
+) -> Diagnostic {
+    let label = format!("expected {}, found {}", expected, operand.expression_type);
+    Diagnostic::error(code, message, operand.span).with_label(&label)
+}
+
+fn malformed(node: &TreeNode) -> Diagnostic {
+    let message = format!(
+        "malformed syntax tree: {:?} is missing an operand",
+        node.kind
+    );
+    Diagnostic::error("E0200", message, node.span)
 }
 
 impl Analyzer {
@@ -131,9 +460,12 @@ impl Analyzer {
     }
 
     pub fn build_symbol_table(&mut self, node: &Option<Box<TreeNode>>) -> SymTable {
-        let mut sym_table = SymTable::new();
-        self.insert_sym_table(&mut sym_table, node, insert_node, insert_empty);
-        sym_table
+        let mut builder = SymbolTableBuilder {
+            analyzer: self,
+            sym_table: SymTable::new(),
+        };
+        builder.visit_sequence(node.as_deref());
+        builder.sym_table
     }
 
     fn add_location(&mut self) -> i32 {
@@ -142,65 +474,53 @@ impl Analyzer {
         val
     }
 
-    fn insert_sym_table(
-        &mut self,
-        sym_table: &mut SymTable,
-        node: &Option<Box<TreeNode>>,
-        pre_order: InsertCallback,
-        post_order: InsertEmptyCallback,
-    ) {
-        if node.is_some() {
-            pre_order(self, sym_table, node);
-
-            if let Some(ref node1) = node {
-                for elem in node1.child.iter() {
-                    self.insert_sym_table(sym_table, elem, pre_order, post_order);
-                }
-            }
-
-            post_order(node);
+    /// Warns at every use of a variable that is not assigned on every path
+    /// leading to it.
+    pub fn check_assignments(node: &Option<Box<TreeNode>>) -> Vec<Diagnostic> {
+        let mut analysis = DefiniteAssignment::default();
+        analysis.visit_sequence(node.as_deref());
+        analysis.warnings
+    }
 
-            if let Some(ref node1) = node {
-                self.insert_sym_table(sym_table, &node1.sibling, pre_order, post_order);
-            }
-        }
+    /// Warns about variables whose value is never used and about
+    /// assignments that are always overwritten before being read.
+    pub fn check_unused(node: &Option<Box<TreeNode>>, sym_table: &SymTable) -> Vec<Diagnostic> {
+        liveness::check_dead_stores(node.as_deref(), sym_table)
     }
 
+    /// Type checks the tree, failing with the first type error.
     pub fn type_check(node: &mut Option<Box<TreeNode>>) -> Result<()> {
-        Self::type_traverse(node, type_empty, type_node)
+        match Self::type_check_all(node).into_iter().next() {
+            Some(diagnostic) => Err(diagnostic.into()),
+            None => Ok(()),
+        }
     }
 
-    fn type_traverse(
-        node: &mut Option<Box<TreeNode>>,
-        pre_order: TypeEmptyCallback,
-        post_order: TypeCheckCallback,
-    ) -> Result<()> {
-        match node {
-            Some(_) => {
-                pre_order(node)?;
-
-                if let Some(node1) = node {
-                    for elem in node1.child.iter_mut() {
-                        Self::type_traverse(elem, pre_order, post_order)?;
-                    }
-                }
-
-                post_order(node)?;
-                if let Some(node1) = node {
-                    Self::type_traverse(&mut node1.sibling, pre_order, post_order)?;
-                }
-            }
-            None => {}
-        }
-        Ok(())
+    /// Type checks the whole tree and returns every type error in source
+    /// order. Each expression is annotated with its type either way.
+    pub fn type_check_all(node: &mut Option<Box<TreeNode>>) -> Vec<Diagnostic> {
+        let mut checker = TypeChecker {
+            vars: TypeInference::infer(node.as_deref()),
+            errors: vec![],
+        };
+        // the checker records its errors instead of stopping at the first
+        checker.visit_sequence_mut(node.as_deref_mut());
+        // children are checked before their parents
+        checker
+            .errors
+            .sort_by_key(|diagnostic| diagnostic.span.start);
+        checker.errors
     }
 }
 
 #[cfg(test)]
 mod tests {
     use crate::analyzer::Analyzer;
+    use crate::ast::ExpressionType;
+    use crate::diagnostic::Diagnostic;
+    use crate::driver::scan;
     use crate::parser::Parser;
-    use crate::token::Token;
+    use crate::token::{Span, Token};
     use anyhow::Result;
 
     #[test]
@@ -244,7 +564,7 @@ mod tests {
         let node = parser.parse()?;
         let mut analyzer = Analyzer::new();
         let sym_table = analyzer.build_symbol_table(&Some(Box::new(node)));
-        println!("{:#?}", sym_table);
+        println!("{}", sym_table);
         Ok(())
     }
 
@@ -292,4 +612,144 @@ mod tests {
         println!("{}", node1.unwrap());
         Ok(())
     }
+
+    #[test]
+    fn test_type_check_diagnostic() -> Result<()> {
+        let node = Parser::new(scan("read x;\nif x then write 1 end")).parse()?;
+        let err = Analyzer::type_check(&mut Some(Box::new(node))).unwrap_err();
+        let diagnostic = err.downcast_ref::<Diagnostic>().unwrap();
+        assert_eq!(diagnostic.code, "E0201");
+        assert_eq!(
+            diagnostic.message,
+            "if-condition must be boolean, found integer"
+        );
+        assert_eq!(diagnostic.span, Span::new(11, 12, 2, 4));
+
+        let node = Parser::new(scan("write 1 + (2 < 3)")).parse()?;
+        let err = Analyzer::type_check(&mut Some(Box::new(node))).unwrap_err();
+        let diagnostic = err.downcast_ref::<Diagnostic>().unwrap();
+        assert_eq!(
+            diagnostic.message,
+            "operands of `+` must be integers, found boolean"
+        );
+        assert_eq!(diagnostic.span, Span::new(11, 16, 1, 12));
+
+        let node = Parser::new(scan("if not 1 and (2 > 1) then write 1 end")).parse()?;
+        let err = Analyzer::type_check(&mut Some(Box::new(node))).unwrap_err();
+        let diagnostic = err.downcast_ref::<Diagnostic>().unwrap();
+        assert_eq!(
+            diagnostic.message,
+            "operand of `not` must be boolean, found integer"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_type_check_all() -> Result<()> {
+        let source = "x := 1; x := 1 < 2;\nwrite (x = 1) + (2 < x) * 3;\nif x then write x - 1 end";
+        let node = Parser::new(scan(source)).parse()?;
+        let mut root = Some(Box::new(node));
+        let errors = Analyzer::type_check_all(&mut root);
+        let codes: Vec<&str> = errors.iter().map(|d| d.code).collect();
+        assert_eq!(codes, vec!["E0203", "E0205", "E0205", "E0201"]);
+        let lines: Vec<usize> = errors.iter().map(|d| d.span.line).collect();
+        assert_eq!(lines, vec![1, 2, 2, 3]);
+
+        // the expressions are typed even where an operand is wrong
+        let assign = root.as_ref().unwrap().sibling.as_ref().unwrap();
+        let write = assign.sibling.as_ref().unwrap();
+        let sum = write.child[0].as_ref().unwrap();
+        assert_eq!(sum.expression_type, ExpressionType::Integer);
+        Ok(())
+    }
+
+    #[test]
+    fn test_boolean_variables() -> Result<()> {
+        let source = "b := 1 < 2;
+c := b and not false;
+ok := (b = c) or (d <> true);
+d := c;
+write b + 1;
+read c;
+b := 3;
+if ok then write 1 end";
+        let node = Parser::new(scan(source)).parse()?;
+        let mut root = Some(Box::new(node));
+        let errors = Analyzer::type_check_all(&mut root);
+        let found: Vec<(&str, &str, usize)> = errors
+            .iter()
+            .map(|d| (d.code, d.message.as_str(), d.span.line))
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                (
+                    "E0205",
+                    "operands of `+` must be integers, found boolean",
+                    5
+                ),
+                (
+                    "E0208",
+                    "cannot read into boolean variable `c`, only integers can be read",
+                    6
+                ),
+                (
+                    "E0203",
+                    "cannot assign integer value to boolean variable `b`",
+                    7
+                ),
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_infer_from_first_assignment() -> Result<()> {
+        // `b` takes its type from `b := c`, which is only known once the
+        // later `c := x < 1` has been seen
+        let source = "read x; b := c; c := x < 1; d := 1; b := d; if b then write 1 end";
+        let node = Parser::new(scan(source)).parse()?;
+        let mut root = Some(Box::new(node));
+        let errors = Analyzer::type_check_all(&mut root);
+        let found: Vec<(&str, &str)> = errors
+            .iter()
+            .map(|d| (d.code, d.message.as_str()))
+            .collect();
+        assert_eq!(
+            found,
+            vec![(
+                "E0203",
+                "cannot assign integer value to boolean variable `b`"
+            )]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_check_assignments() -> Result<()> {
+        let source = "read x;
+if x < 1 then y := 1; z := 1 else y := 2 end;
+write y + z;
+repeat w := x; x := x - 1 until w = 0;
+write w;
+repeat write v; v := 1 until v = 1;
+while x < 1 do u := 1 end;
+write u";
+        let node = Parser::new(scan(source)).parse()?;
+        let warnings = Analyzer::check_assignments(&Some(Box::new(node)));
+        let used: Vec<(&str, usize)> = warnings
+            .iter()
+            .map(|d| (d.message.as_str(), d.span.line))
+            .collect();
+        assert_eq!(
+            used,
+            vec![
+                ("`z` may be used before it is assigned", 3),
+                ("`v` may be used before it is assigned", 6),
+                ("`u` may be used before it is assigned", 8),
+            ]
+        );
+        assert!(warnings.iter().all(|d| !d.is_error()));
+        Ok(())
+    }
 }
@@ -1,4 +1,8 @@
-use crate::token::Token;
+pub mod serialize;
+pub mod typed;
+pub mod visit;
+
+use crate::token::{Span, Token};
 use std::fmt::{Display, Formatter};
 
 #[derive(PartialEq, Debug, Clone)]
@@ -11,6 +15,8 @@ pub enum NodeKind {
 pub enum StatementKind {
     IfK,
     RepeatK,
+    WhileK,
+    ForK,
     AssignK,
     ReadK,
     WriteK,
@@ -30,6 +36,16 @@ pub enum ExpressionType {
     Boolean,
 }
 
+impl Display for ExpressionType {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match &self {
+            ExpressionType::Void => write!(f, "void"),
+            ExpressionType::Integer => write!(f, "integer"),
+            ExpressionType::Boolean => write!(f, "boolean"),
+        }
+    }
+}
+
 #[derive(PartialEq, Debug, Clone)]
 pub enum Kind {
     Statement(StatementKind),
@@ -40,6 +56,7 @@ pub enum Kind {
 pub enum Attr {
     Op(Token),
     Val(i32),
+    Bool(bool),
     Name(String),
 }
 
@@ -48,6 +65,7 @@ impl Display for Attr {
         match &self {
             Attr::Op(token) => write!(f, "{}", *token)?,
             Attr::Val(val) => write!(f, "{}", *val)?,
+            Attr::Bool(b) => write!(f, "{}", *b)?,
             Attr::Name(name) => write!(f, "{}", name)?,
         }
         Ok(())
@@ -59,8 +77,9 @@ pub struct TreeNode {
     pub child: Vec<Option<Box<TreeNode>>>,
     pub sibling: Option<Box<TreeNode>>,
 
-    // XXX FIXME YSG
+    // line of `span.start`, kept for the symbol table line lists
     pub line_number: i32,
+    pub span: Span,
     pub expression_type: ExpressionType,
     pub kind: Kind,
     pub attr: Attr,
@@ -68,11 +87,16 @@ pub struct TreeNode {
 
 impl TreeNode {
     pub fn new_statement_node(kind: StatementKind) -> Self {
-        let child = vec![None, None, None];
+        // a `for` has its initial assignment, bound, step and body
+        let child = match kind {
+            StatementKind::ForK => vec![None, None, None, None],
+            _ => vec![None, None, None],
+        };
         Self {
             child,
             sibling: None,
             line_number: 0,
+            span: Span::default(),
             expression_type: ExpressionType::Void,
             kind: Kind::Statement(kind),
             attr: Attr::Val(0),
@@ -85,42 +109,61 @@ impl TreeNode {
             child,
             sibling: None,
             line_number: 0,
+            span: Span::default(),
             expression_type: ExpressionType::Void,
             kind: Kind::Expression(kind),
             attr: Attr::Val(0),
         }
     }
 
-    // XXX FIXME YSG implement with loop
+    /// Whether the node is an operator applied to a single operand, like
+    /// `not x`; the operand is in the first child slot.
+    pub fn is_unary(&self) -> bool {
+        self.kind == Kind::Expression(ExpressionKind::Opk) && self.child[1].is_none()
+    }
+
+    /// Records the source text the node was parsed from.
+    pub fn set_span(&mut self, span: Span) {
+        self.span = span;
+        self.line_number = span.line as i32;
+    }
+
+    // siblings share the indentation of the first statement in the sequence
     fn print_tree(&self, f: &mut Formatter<'_>, indent_count: &mut usize) -> std::fmt::Result {
         *indent_count += 2;
-        // writeln!(f, "cnt = {} ", *indent_count)?;
-
-        {
+        let mut node = Some(self);
+        while let Some(t) = node {
             let str = " ".repeat(*indent_count);
 
-            match &self.kind {
+            match &t.kind {
                 Kind::Statement(stmt) => match stmt {
                     StatementKind::IfK => writeln!(f, "{} If", str)?,
                     StatementKind::RepeatK => writeln!(f, "{} Repeat", str)?,
-                    StatementKind::AssignK => writeln!(f, "{} Assign to: {}", str, self.attr)?,
-                    StatementKind::ReadK => writeln!(f, "{} Read: {}", str, self.attr)?,
+                    StatementKind::WhileK => writeln!(f, "{} While", str)?,
+                    StatementKind::ForK => match &t.attr {
+                        Attr::Op(Token::Downto) => writeln!(f, "{} For downto", str)?,
+                        _ => writeln!(f, "{} For to", str)?,
+                    },
+                    StatementKind::AssignK => writeln!(f, "{} Assign to: {}", str, t.attr)?,
+                    StatementKind::ReadK => writeln!(f, "{} Read: {}", str, t.attr)?,
                     StatementKind::WriteK => writeln!(f, "{} Write", str)?,
                 },
                 Kind::Expression(expr) => match expr {
-                    ExpressionKind::Opk => writeln!(f, "{} Op: {}", str, self.attr)?,
-                    ExpressionKind::ConstK => writeln!(f, "{} const: {}", str, self.attr)?,
-                    ExpressionKind::IdK => writeln!(f, "{} Id: {}", str, self.attr)?,
+                    ExpressionKind::Opk => match &t.attr {
+                        // keywords such as `and` are printed as written
+                        Attr::Op(op) => writeln!(f, "{} Op: {}", str, op.text())?,
+                        attr => writeln!(f, "{} Op: {}", str, attr)?,
+                    },
+                    ExpressionKind::ConstK => writeln!(f, "{} const: {}", str, t.attr)?,
+                    ExpressionKind::IdK => writeln!(f, "{} Id: {}", str, t.attr)?,
                 },
             }
 
-            for elem in self.child.iter().flatten() {
+            for elem in t.child.iter().flatten() {
                 elem.print_tree(f, indent_count)?;
             }
 
-            if let Some(node) = &self.sibling {
-                node.print_tree(f, indent_count)?;
-            }
+            node = t.sibling.as_deref();
         }
         *indent_count -= 2;
         Ok(())
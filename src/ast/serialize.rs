@@ -0,0 +1,464 @@
+use crate::ast::{Attr, ExpressionKind, ExpressionType, Kind, StatementKind, TreeNode};
+use crate::json::Json;
+use crate::scanner::tokenize;
+use crate::token::{Span, Token};
+use anyhow::Result;
+
+// Syntax trees are saved as the statement sequence starting at the root.
+// Each node lists its kind, attribute, type, line number and span, then its
+// three child slots, each either null/nil or a statement or expression
+// sequence. Sibling chains become arrays, so the nesting follows the program
+// structure rather than its length.
+
+/// Serializes the sequence starting at `tree` as indented JSON, an array
+/// of objects like this one for `read x`:
+///
+/// ```text
+/// {"kind": "ReadK", "attr": {"name": "x"}, "type": "void", "line": 1,
+///  "span": {"start": 0, "end": 6, "line": 1, "col": 1},
+///  "children": [null, null, null]}
+/// ```
+///
+/// The attribute is one of `{"op": "<"}`, `{"val": 1}`, `{"bool": true}` or
+/// `{"name": "x"}`.
+pub fn to_json(tree: &TreeNode) -> String {
+    sequence_to_json(Some(tree)).pretty()
+}
+
+/// Reads back a tree written by [`to_json`].
+pub fn from_json(text: &str) -> Result<TreeNode> {
+    let json = Json::parse(text)?;
+    sequence_from_json(&json)?.ok_or_else(|| anyhow::format_err!("empty program"))
+}
+
+/// Serializes the sequence starting at `tree` as a single-line S-expression.
+/// Each node reads `(kind attr type line (start end line col) children...)`
+/// with trailing `nil` children left out; names are symbols, values are
+/// integers, `true` or `false` and operators are strings:
+///
+/// ```text
+/// ((WriteK 0 void 1 (0 7 1 1) ((ConstK 1 integer 1 (6 7 1 7)))))
+/// ```
+pub fn to_sexp(tree: &TreeNode) -> String {
+    let mut out = String::new();
+    sequence_to_sexp(&mut out, tree);
+    out
+}
+
+/// Reads back a tree written by [`to_sexp`].
+pub fn from_sexp(text: &str) -> Result<TreeNode> {
+    let mut reader = SexpReader {
+        chars: text.chars().peekable(),
+    };
+    let sexp = reader.read()?;
+    if let Some(c) = reader.next_non_space() {
+        return Err(anyhow::format_err!("unexpected `{}` after S-expression", c));
+    }
+    sequence_from_sexp(&sexp)?.ok_or_else(|| anyhow::format_err!("empty program"))
+}
+
+fn kind_name(kind: &Kind) -> String {
+    match kind {
+        Kind::Statement(stmt) => format!("{:?}", stmt),
+        Kind::Expression(expr) => format!("{:?}", expr),
+    }
+}
+
+fn kind_from_name(name: &str) -> Result<Kind> {
+    Ok(match name {
+        "IfK" => Kind::Statement(StatementKind::IfK),
+        "RepeatK" => Kind::Statement(StatementKind::RepeatK),
+        "WhileK" => Kind::Statement(StatementKind::WhileK),
+        "ForK" => Kind::Statement(StatementKind::ForK),
+        "AssignK" => Kind::Statement(StatementKind::AssignK),
+        "ReadK" => Kind::Statement(StatementKind::ReadK),
+        "WriteK" => Kind::Statement(StatementKind::WriteK),
+        "Opk" => Kind::Expression(ExpressionKind::Opk),
+        "ConstK" => Kind::Expression(ExpressionKind::ConstK),
+        "IdK" => Kind::Expression(ExpressionKind::IdK),
+        _ => return Err(anyhow::format_err!("unknown node kind `{}`", name)),
+    })
+}
+
+fn type_from_name(name: &str) -> Result<ExpressionType> {
+    Ok(match name {
+        "void" => ExpressionType::Void,
+        "integer" => ExpressionType::Integer,
+        "boolean" => ExpressionType::Boolean,
+        _ => return Err(anyhow::format_err!("unknown expression type `{}`", name)),
+    })
+}
+
+fn op_from_text(text: &str) -> Result<Token> {
+    match tokenize(text).as_slice() {
+        [op, Token::EndFile] if !matches!(op, Token::Id(_) | Token::Num(_) | Token::Error(_)) => {
+            Ok(op.clone())
+        }
+        _ => Err(anyhow::format_err!("unknown operator `{}`", text)),
+    }
+}
+
+fn node(kind: Kind, attr: Attr, ty: ExpressionType, line: i64, span: Span) -> TreeNode {
+    let mut node = match kind {
+        Kind::Statement(stmt) => TreeNode::new_statement_node(stmt),
+        Kind::Expression(expr) => TreeNode::new_expression_node(expr),
+    };
+    node.attr = attr;
+    node.expression_type = ty;
+    node.span = span;
+    node.line_number = line as i32;
+    node
+}
+
+// links `nodes` into a sibling chain
+fn chain(nodes: Vec<TreeNode>) -> Option<TreeNode> {
+    let mut tree: Option<TreeNode> = None;
+    for mut node in nodes.into_iter().rev() {
+        node.sibling = tree.map(Box::new);
+        tree = Some(node);
+    }
+    tree
+}
+
+fn sequence_to_json(mut node: Option<&TreeNode>) -> Json {
+    let mut items = vec![];
+    while let Some(t) = node {
+        items.push(node_to_json(t));
+        node = t.sibling.as_deref();
+    }
+    Json::Array(items)
+}
+
+fn node_to_json(node: &TreeNode) -> Json {
+    let attr = match &node.attr {
+        // operators are written as they appear in the source
+        Attr::Op(op) => ("op", Json::String(op.text())),
+        Attr::Val(val) => ("val", Json::Number(*val as i64)),
+        Attr::Bool(b) => ("bool", Json::Bool(*b)),
+        Attr::Name(name) => ("name", Json::String(name.clone())),
+    };
+    let span = node.span;
+    let children = node
+        .child
+        .iter()
+        .map(|child| match child {
+            Some(child) => sequence_to_json(Some(child)),
+            None => Json::Null,
+        })
+        .collect();
+    Json::Object(vec![
+        ("kind".into(), Json::String(kind_name(&node.kind))),
+        ("attr".into(), Json::Object(vec![(attr.0.into(), attr.1)])),
+        (
+            "type".into(),
+            Json::String(node.expression_type.to_string()),
+        ),
+        ("line".into(), Json::Number(node.line_number as i64)),
+        (
+            "span".into(),
+            Json::Object(vec![
+                ("start".into(), Json::Number(span.start as i64)),
+                ("end".into(), Json::Number(span.end as i64)),
+                ("line".into(), Json::Number(span.line as i64)),
+                ("col".into(), Json::Number(span.col as i64)),
+            ]),
+        ),
+        ("children".into(), Json::Array(children)),
+    ])
+}
+
+fn sequence_from_json(json: &Json) -> Result<Option<TreeNode>> {
+    let items = json
+        .as_array()
+        .ok_or_else(|| anyhow::format_err!("expected an array of nodes, found {}", json))?;
+    let nodes = items
+        .iter()
+        .map(node_from_json)
+        .collect::<Result<Vec<_>>>()?;
+    Ok(chain(nodes))
+}
+
+fn node_from_json(json: &Json) -> Result<TreeNode> {
+    let field = |key: &str| {
+        json.get(key)
+            .ok_or_else(|| anyhow::format_err!("node is missing `{}`: {}", key, json))
+    };
+    let int = |value: &Json| {
+        value
+            .as_i64()
+            .ok_or_else(|| anyhow::format_err!("expected an integer, found {}", value))
+    };
+    let string = |value: &Json| {
+        value
+            .as_str()
+            .map(String::from)
+            .ok_or_else(|| anyhow::format_err!("expected a string, found {}", value))
+    };
+
+    let kind = kind_from_name(&string(field("kind")?)?)?;
+    let attr = match field("attr")? {
+        Json::Object(members) if members.len() == 1 => {
+            let (key, value) = &members[0];
+            match key.as_str() {
+                "op" => Attr::Op(op_from_text(&string(value)?)?),
+                "val" => Attr::Val(int(value)? as i32),
+                "bool" => match value {
+                    Json::Bool(b) => Attr::Bool(*b),
+                    value => {
+                        return Err(anyhow::format_err!("expected a boolean, found {}", value))
+                    }
+                },
+                "name" => Attr::Name(string(value)?),
+                key => return Err(anyhow::format_err!("unknown attribute `{}`", key)),
+            }
+        }
+        attr => return Err(anyhow::format_err!("malformed attribute {}", attr)),
+    };
+    let ty = type_from_name(&string(field("type")?)?)?;
+    let span = field("span")?;
+    let span_field = |key: &str| -> Result<usize> {
+        let value = span
+            .get(key)
+            .ok_or_else(|| anyhow::format_err!("span is missing `{}`: {}", key, span))?;
+        Ok(int(value)? as usize)
+    };
+    let span = Span::new(
+        span_field("start")?,
+        span_field("end")?,
+        span_field("line")?,
+        span_field("col")?,
+    );
+    let mut node = node(kind, attr, ty, int(field("line")?)?, span);
+
+    let children = field("children")?;
+    let children = children
+        .as_array()
+        .filter(|children| children.len() <= node.child.len())
+        .ok_or_else(|| anyhow::format_err!("malformed children {}", children))?;
+    for (slot, child) in node.child.iter_mut().zip(children) {
+        if *child != Json::Null {
+            *slot = sequence_from_json(child)?.map(Box::new);
+        }
+    }
+    Ok(node)
+}
+
+fn sequence_to_sexp(out: &mut String, tree: &TreeNode) {
+    out.push('(');
+    let mut node = Some(tree);
+    while let Some(t) = node {
+        if !std::ptr::eq(t, tree) {
+            out.push(' ');
+        }
+        node_to_sexp(out, t);
+        node = t.sibling.as_deref();
+    }
+    out.push(')');
+}
+
+fn node_to_sexp(out: &mut String, node: &TreeNode) {
+    let attr = match &node.attr {
+        Attr::Op(op) => Json::String(op.text()).to_string(),
+        attr => attr.to_string(),
+    };
+    let span = node.span;
+    out.push_str(&format!(
+        "({} {} {} {} ({} {} {} {})",
+        kind_name(&node.kind),
+        attr,
+        node.expression_type,
+        node.line_number,
+        span.start,
+        span.end,
+        span.line,
+        span.col
+    ));
+    let used = node
+        .child
+        .iter()
+        .rposition(Option::is_some)
+        .map_or(0, |i| i + 1);
+    for child in node.child[..used].iter() {
+        out.push(' ');
+        match child {
+            Some(child) => sequence_to_sexp(out, child),
+            None => out.push_str("nil"),
+        }
+    }
+    out.push(')');
+}
+
+#[derive(Debug, PartialEq)]
+enum Sexp {
+    Atom(String),
+    Str(String),
+    List(Vec<Sexp>),
+}
+
+struct SexpReader<'a> {
+    chars: std::iter::Peekable<std::str::Chars<'a>>,
+}
+
+impl SexpReader<'_> {
+    fn next_non_space(&mut self) -> Option<char> {
+        while self.chars.next_if(|c| c.is_whitespace()).is_some() {}
+        self.chars.next()
+    }
+
+    fn read(&mut self) -> Result<Sexp> {
+        match self.next_non_space() {
+            Some('(') => {
+                let mut items = vec![];
+                loop {
+                    while self.chars.next_if(|c| c.is_whitespace()).is_some() {}
+                    if self.chars.next_if_eq(&')').is_some() {
+                        return Ok(Sexp::List(items));
+                    }
+                    if self.chars.peek().is_none() {
+                        return Err(anyhow::format_err!("unclosed `(`"));
+                    }
+                    items.push(self.read()?);
+                }
+            }
+            Some('"') => {
+                let mut text = String::new();
+                loop {
+                    match self.chars.next() {
+                        Some('"') => return Ok(Sexp::Str(text)),
+                        Some('\\') => text.extend(self.chars.next()),
+                        Some(c) => text.push(c),
+                        None => return Err(anyhow::format_err!("unterminated string")),
+                    }
+                }
+            }
+            Some(')') => Err(anyhow::format_err!("unexpected `)`")),
+            Some(c) => {
+                let mut atom = c.to_string();
+                while let Some(c) = self
+                    .chars
+                    .next_if(|c| !c.is_whitespace() && *c != '(' && *c != ')')
+                {
+                    atom.push(c);
+                }
+                Ok(Sexp::Atom(atom))
+            }
+            None => Err(anyhow::format_err!("unexpected end of input")),
+        }
+    }
+}
+
+fn sequence_from_sexp(sexp: &Sexp) -> Result<Option<TreeNode>> {
+    match sexp {
+        Sexp::List(items) => {
+            let nodes = items
+                .iter()
+                .map(node_from_sexp)
+                .collect::<Result<Vec<_>>>()?;
+            Ok(chain(nodes))
+        }
+        Sexp::Atom(atom) if atom == "nil" => Ok(None),
+        _ => Err(anyhow::format_err!(
+            "expected a list of nodes, found {:?}",
+            sexp
+        )),
+    }
+}
+
+fn node_from_sexp(sexp: &Sexp) -> Result<TreeNode> {
+    let malformed = || anyhow::format_err!("malformed node {:?}", sexp);
+    let Sexp::List(items) = sexp else {
+        return Err(malformed());
+    };
+    let int = |sexp: &Sexp| match sexp {
+        Sexp::Atom(atom) => atom.parse::<i64>().map_err(|_| malformed()),
+        _ => Err(malformed()),
+    };
+    let (kind, attr, ty, line, span, children) = match items.as_slice() {
+        [Sexp::Atom(kind), attr, Sexp::Atom(ty), line, Sexp::List(span), children @ ..]
+            if span.len() == 4 =>
+        {
+            (kind, attr, ty, line, span, children)
+        }
+        _ => return Err(malformed()),
+    };
+    let attr = match attr {
+        Sexp::Str(op) => Attr::Op(op_from_text(op)?),
+        // `true` and `false` are reserved, so they cannot be names
+        Sexp::Atom(atom) => match (atom.as_str(), atom.parse::<i32>()) {
+            ("true", _) => Attr::Bool(true),
+            ("false", _) => Attr::Bool(false),
+            (_, Ok(val)) => Attr::Val(val),
+            (_, Err(_)) => Attr::Name(atom.clone()),
+        },
+        Sexp::List(_) => return Err(malformed()),
+    };
+    let span = Span::new(
+        int(&span[0])? as usize,
+        int(&span[1])? as usize,
+        int(&span[2])? as usize,
+        int(&span[3])? as usize,
+    );
+    let mut node = node(
+        kind_from_name(kind)?,
+        attr,
+        type_from_name(ty)?,
+        int(line)?,
+        span,
+    );
+    if children.len() > node.child.len() {
+        return Err(malformed());
+    }
+    for (slot, child) in node.child.iter_mut().zip(children) {
+        *slot = sequence_from_sexp(child)?.map(Box::new);
+    }
+    Ok(node)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ast::serialize::{from_json, from_sexp, to_json, to_sexp};
+    use crate::driver::compile;
+    use anyhow::Result;
+
+    const PROGRAM: &str = "read x;
+if 0 < x then
+  fact := 1;
+  repeat
+    fact := fact * x;
+    x := x - 1
+  until x = 0;
+  write fact
+else
+  write 0
+end;
+for i := x downto 1 step 2 do write i end;
+done := true";
+
+    #[test]
+    fn test_json_round_trip() -> Result<()> {
+        let tree = compile(PROGRAM)?.tree;
+        let json = to_json(&tree);
+        assert!(json.starts_with("[\n  {\n    \"kind\": \"ReadK\",\n"));
+        assert!(json.contains("\"op\": \"<\""));
+        assert_eq!(from_json(&json)?, tree);
+        assert!(from_json("[]").is_err());
+        assert!(from_json(r#"[{"kind": "IfK"}]"#).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_sexp_round_trip() -> Result<()> {
+        let tree = compile("write 1")?.tree;
+        assert_eq!(
+            to_sexp(&tree),
+            "((WriteK 0 void 1 (0 7 1 1) ((ConstK 1 integer 1 (6 7 1 7)))))"
+        );
+
+        let tree = compile(PROGRAM)?.tree;
+        let sexp = to_sexp(&tree);
+        assert!(sexp.contains("(Opk \"<\" boolean 2 (11 16 2 4)"));
+        assert_eq!(from_sexp(&sexp)?, tree);
+        assert!(from_sexp("((WriteK 0 void 1 (0 7 1 1))").is_err());
+        Ok(())
+    }
+}
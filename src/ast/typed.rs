@@ -0,0 +1,414 @@
+use crate::ast::{Attr, ExpressionKind, ExpressionType, Kind, StatementKind, TreeNode};
+use crate::token::{Span, Token};
+use anyhow::Result;
+
+/// A TINY statement with its parts named instead of stored in `child` slots.
+///
+/// Sequences are plain `Vec`s; an empty `else_branch` means the `if` has
+/// no `else` part. The `init` of a `for` is the `Assign` of its first value
+/// to the loop variable.
+#[derive(PartialEq, Debug, Clone)]
+pub enum Stmt {
+    If {
+        cond: Expr,
+        then_branch: Vec<Stmt>,
+        else_branch: Vec<Stmt>,
+        span: Span,
+    },
+    Repeat {
+        body: Vec<Stmt>,
+        cond: Expr,
+        span: Span,
+    },
+    While {
+        cond: Expr,
+        body: Vec<Stmt>,
+        span: Span,
+    },
+    For {
+        init: Box<Stmt>,
+        downto: bool,
+        bound: Expr,
+        step: Option<Expr>,
+        body: Vec<Stmt>,
+        span: Span,
+    },
+    Assign {
+        name: String,
+        value: Expr,
+        span: Span,
+    },
+    Read {
+        name: String,
+        span: Span,
+    },
+    Write {
+        value: Expr,
+        span: Span,
+    },
+}
+
+/// A TINY expression. `ty` is `Void` until the type checker has run.
+#[derive(PartialEq, Debug, Clone)]
+pub enum Expr {
+    Const {
+        value: i32,
+        span: Span,
+        ty: ExpressionType,
+    },
+    Bool {
+        value: bool,
+        span: Span,
+        ty: ExpressionType,
+    },
+    Var {
+        name: String,
+        span: Span,
+        ty: ExpressionType,
+    },
+    Binary {
+        op: Token,
+        lhs: Box<Expr>,
+        rhs: Box<Expr>,
+        span: Span,
+        ty: ExpressionType,
+    },
+    Unary {
+        op: Token,
+        operand: Box<Expr>,
+        span: Span,
+        ty: ExpressionType,
+    },
+}
+
+impl Stmt {
+    pub fn span(&self) -> Span {
+        match self {
+            Stmt::If { span, .. }
+            | Stmt::Repeat { span, .. }
+            | Stmt::While { span, .. }
+            | Stmt::For { span, .. }
+            | Stmt::Assign { span, .. }
+            | Stmt::Read { span, .. }
+            | Stmt::Write { span, .. } => *span,
+        }
+    }
+}
+
+impl Expr {
+    pub fn span(&self) -> Span {
+        match self {
+            Expr::Const { span, .. }
+            | Expr::Bool { span, .. }
+            | Expr::Var { span, .. }
+            | Expr::Binary { span, .. }
+            | Expr::Unary { span, .. } => *span,
+        }
+    }
+
+    pub fn ty(&self) -> &ExpressionType {
+        match self {
+            Expr::Const { ty, .. }
+            | Expr::Bool { ty, .. }
+            | Expr::Var { ty, .. }
+            | Expr::Binary { ty, .. }
+            | Expr::Unary { ty, .. } => ty,
+        }
+    }
+}
+
+/// Converts the statement sequence starting at `tree`. Fails on partial
+/// trees, such as the ones `Parser::parse_recovering` returns after an error.
+pub fn from_tree(tree: &TreeNode) -> Result<Vec<Stmt>> {
+    sequence(Some(tree))
+}
+
+/// Builds the `TreeNode` sequence for `stmts`, `None` when it is empty.
+/// Converting a parsed tree with [`from_tree`] and back gives the same tree.
+pub fn to_tree(stmts: &[Stmt]) -> Option<TreeNode> {
+    let mut tree: Option<TreeNode> = None;
+    for stmt in stmts.iter().rev() {
+        let mut node = TreeNode::from(stmt);
+        node.sibling = tree.map(Box::new);
+        tree = Some(node);
+    }
+    tree
+}
+
+fn sequence(mut node: Option<&TreeNode>) -> Result<Vec<Stmt>> {
+    let mut stmts = vec![];
+    while let Some(t) = node {
+        stmts.push(Stmt::try_from(t)?);
+        node = t.sibling.as_deref();
+    }
+    Ok(stmts)
+}
+
+fn expr_child(node: &TreeNode, idx: usize) -> Result<Expr> {
+    match &node.child[idx] {
+        Some(child) => Expr::try_from(child.as_ref()),
+        None => Err(anyhow::format_err!("missing operand in {}", node)),
+    }
+}
+
+fn name_of(node: &TreeNode) -> Result<String> {
+    match &node.attr {
+        Attr::Name(name) => Ok(name.clone()),
+        attr => Err(anyhow::format_err!(
+            "expected a variable name, found {}",
+            attr
+        )),
+    }
+}
+
+impl TryFrom<&TreeNode> for Stmt {
+    type Error = anyhow::Error;
+
+    /// Converts `node` alone; its siblings are left out.
+    fn try_from(node: &TreeNode) -> Result<Self> {
+        let stmt = match &node.kind {
+            Kind::Statement(stmt) => stmt,
+            Kind::Expression(_) => {
+                return Err(anyhow::format_err!("expected a statement, found {}", node))
+            }
+        };
+        let span = node.span;
+        Ok(match stmt {
+            StatementKind::IfK => Stmt::If {
+                cond: expr_child(node, 0)?,
+                then_branch: sequence(node.child[1].as_deref())?,
+                else_branch: sequence(node.child[2].as_deref())?,
+                span,
+            },
+            StatementKind::RepeatK => Stmt::Repeat {
+                body: sequence(node.child[0].as_deref())?,
+                cond: expr_child(node, 1)?,
+                span,
+            },
+            StatementKind::WhileK => Stmt::While {
+                cond: expr_child(node, 0)?,
+                body: sequence(node.child[1].as_deref())?,
+                span,
+            },
+            StatementKind::ForK => Stmt::For {
+                init: match &node.child[0] {
+                    Some(init) => Box::new(Stmt::try_from(init.as_ref())?),
+                    None => return Err(anyhow::format_err!("missing loop variable in {}", node)),
+                },
+                downto: node.attr == Attr::Op(Token::Downto),
+                bound: expr_child(node, 1)?,
+                step: node.child[2].as_deref().map(Expr::try_from).transpose()?,
+                body: sequence(node.child[3].as_deref())?,
+                span,
+            },
+            StatementKind::AssignK => Stmt::Assign {
+                name: name_of(node)?,
+                value: expr_child(node, 0)?,
+                span,
+            },
+            StatementKind::ReadK => Stmt::Read {
+                name: name_of(node)?,
+                span,
+            },
+            StatementKind::WriteK => Stmt::Write {
+                value: expr_child(node, 0)?,
+                span,
+            },
+        })
+    }
+}
+
+impl TryFrom<&TreeNode> for Expr {
+    type Error = anyhow::Error;
+
+    fn try_from(node: &TreeNode) -> Result<Self> {
+        let expr = match &node.kind {
+            Kind::Expression(expr) => expr,
+            Kind::Statement(_) => {
+                return Err(anyhow::format_err!(
+                    "expected an expression, found {}",
+                    node
+                ))
+            }
+        };
+        let span = node.span;
+        let ty = node.expression_type.clone();
+        match (expr, &node.attr) {
+            (ExpressionKind::ConstK, Attr::Val(value)) => Ok(Expr::Const {
+                value: *value,
+                span,
+                ty,
+            }),
+            (ExpressionKind::ConstK, Attr::Bool(value)) => Ok(Expr::Bool {
+                value: *value,
+                span,
+                ty,
+            }),
+            (ExpressionKind::IdK, Attr::Name(name)) => Ok(Expr::Var {
+                name: name.clone(),
+                span,
+                ty,
+            }),
+            (ExpressionKind::Opk, Attr::Op(op)) if node.is_unary() => Ok(Expr::Unary {
+                op: op.clone(),
+                operand: Box::new(expr_child(node, 0)?),
+                span,
+                ty,
+            }),
+            (ExpressionKind::Opk, Attr::Op(op)) => Ok(Expr::Binary {
+                op: op.clone(),
+                lhs: Box::new(expr_child(node, 0)?),
+                rhs: Box::new(expr_child(node, 1)?),
+                span,
+                ty,
+            }),
+            (_, attr) => Err(anyhow::format_err!(
+                "malformed expression node with attribute {}",
+                attr
+            )),
+        }
+    }
+}
+
+impl From<&Stmt> for TreeNode {
+    fn from(stmt: &Stmt) -> Self {
+        let (kind, attr) = match stmt {
+            Stmt::If { .. } => (StatementKind::IfK, Attr::Val(0)),
+            Stmt::Repeat { .. } => (StatementKind::RepeatK, Attr::Val(0)),
+            Stmt::While { .. } => (StatementKind::WhileK, Attr::Val(0)),
+            Stmt::For { downto: false, .. } => (StatementKind::ForK, Attr::Op(Token::To)),
+            Stmt::For { downto: true, .. } => (StatementKind::ForK, Attr::Op(Token::Downto)),
+            Stmt::Assign { name, .. } => (StatementKind::AssignK, Attr::Name(name.clone())),
+            Stmt::Read { name, .. } => (StatementKind::ReadK, Attr::Name(name.clone())),
+            Stmt::Write { .. } => (StatementKind::WriteK, Attr::Val(0)),
+        };
+        let mut node = TreeNode::new_statement_node(kind);
+        node.attr = attr;
+        node.set_span(stmt.span());
+        match stmt {
+            Stmt::If {
+                cond,
+                then_branch,
+                else_branch,
+                ..
+            } => {
+                node.child[0] = Some(Box::new(cond.into()));
+                node.child[1] = to_tree(then_branch).map(Box::new);
+                node.child[2] = to_tree(else_branch).map(Box::new);
+            }
+            Stmt::Repeat { body, cond, .. } => {
+                node.child[0] = to_tree(body).map(Box::new);
+                node.child[1] = Some(Box::new(cond.into()));
+            }
+            Stmt::While { cond, body, .. } => {
+                node.child[0] = Some(Box::new(cond.into()));
+                node.child[1] = to_tree(body).map(Box::new);
+            }
+            Stmt::For {
+                init,
+                bound,
+                step,
+                body,
+                ..
+            } => {
+                node.child[0] = Some(Box::new(init.as_ref().into()));
+                node.child[1] = Some(Box::new(bound.into()));
+                node.child[2] = step.as_ref().map(|step| Box::new(step.into()));
+                node.child[3] = to_tree(body).map(Box::new);
+            }
+            Stmt::Assign { value, .. } | Stmt::Write { value, .. } => {
+                node.child[0] = Some(Box::new(value.into()));
+            }
+            Stmt::Read { .. } => {}
+        }
+        node
+    }
+}
+
+impl From<&Expr> for TreeNode {
+    fn from(expr: &Expr) -> Self {
+        let mut node = match expr {
+            Expr::Const { value, .. } => {
+                let mut node = TreeNode::new_expression_node(ExpressionKind::ConstK);
+                node.attr = Attr::Val(*value);
+                node
+            }
+            Expr::Bool { value, .. } => {
+                let mut node = TreeNode::new_expression_node(ExpressionKind::ConstK);
+                node.attr = Attr::Bool(*value);
+                node
+            }
+            Expr::Var { name, .. } => {
+                let mut node = TreeNode::new_expression_node(ExpressionKind::IdK);
+                node.attr = Attr::Name(name.clone());
+                node
+            }
+            Expr::Binary { op, lhs, rhs, .. } => {
+                let mut node = TreeNode::new_expression_node(ExpressionKind::Opk);
+                node.attr = Attr::Op(op.clone());
+                node.child[0] = Some(Box::new(lhs.as_ref().into()));
+                node.child[1] = Some(Box::new(rhs.as_ref().into()));
+                node
+            }
+            Expr::Unary { op, operand, .. } => {
+                let mut node = TreeNode::new_expression_node(ExpressionKind::Opk);
+                node.attr = Attr::Op(op.clone());
+                node.child[0] = Some(Box::new(operand.as_ref().into()));
+                node
+            }
+        };
+        node.set_span(expr.span());
+        node.expression_type = expr.ty().clone();
+        node
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ast::typed::{from_tree, to_tree, Expr, Stmt};
+    use crate::ast::ExpressionType;
+    use crate::driver::{compile, scan};
+    use crate::parser::Parser;
+    use crate::token::Token;
+    use anyhow::Result;
+
+    #[test]
+    fn test_round_trip() -> Result<()> {
+        let compilation = compile(
+            "read x;
+if 0 < x then
+  fact := 1;
+  repeat
+    fact := fact * x;
+    x := x - 1
+  until x = 0;
+  write fact
+end",
+        )?;
+        let stmts = from_tree(&compilation.tree)?;
+        assert_eq!(stmts.len(), 2);
+        let Stmt::If {
+            cond,
+            then_branch,
+            else_branch,
+            ..
+        } = &stmts[1]
+        else {
+            panic!("expected an if statement, found {:?}", stmts[1]);
+        };
+        assert!(matches!(cond, Expr::Binary { op: Token::Lt, .. }));
+        assert_eq!(cond.ty(), &ExpressionType::Boolean);
+        assert_eq!(then_branch.len(), 3);
+        assert!(else_branch.is_empty());
+        assert!(matches!(&then_branch[1], Stmt::Repeat { body, .. } if body.len() == 2));
+
+        assert_eq!(to_tree(&stmts), Some(compilation.tree));
+        Ok(())
+    }
+
+    #[test]
+    fn test_partial_tree() {
+        let (tree, _) = Parser::new(scan("x := ; write 1")).parse_recovering();
+        assert!(from_tree(&tree.unwrap()).is_err());
+    }
+}
@@ -0,0 +1,148 @@
+use crate::ast::{Kind, TreeNode};
+
+/// A pass over a syntax tree. Every method defaults to walking the children
+/// of the node in slot order, so an implementation only overrides the
+/// methods for the nodes it is interested in. Work done before calling
+/// `walk_children` happens in preorder, work done after it in postorder.
+pub trait Visitor {
+    /// Visits `node` and its siblings.
+    fn visit_sequence(&mut self, node: Option<&TreeNode>) {
+        walk_sequence(self, node)
+    }
+
+    fn visit_node(&mut self, node: &TreeNode) {
+        walk_node(self, node)
+    }
+
+    fn visit_stmt(&mut self, node: &TreeNode) {
+        walk_children(self, node)
+    }
+
+    fn visit_expr(&mut self, node: &TreeNode) {
+        walk_children(self, node)
+    }
+}
+
+pub fn walk_sequence<V: Visitor + ?Sized>(visitor: &mut V, mut node: Option<&TreeNode>) {
+    while let Some(t) = node {
+        visitor.visit_node(t);
+        node = t.sibling.as_deref();
+    }
+}
+
+// dispatches on the node kind
+pub fn walk_node<V: Visitor + ?Sized>(visitor: &mut V, node: &TreeNode) {
+    match node.kind {
+        Kind::Statement(_) => visitor.visit_stmt(node),
+        Kind::Expression(_) => visitor.visit_expr(node),
+    }
+}
+
+// every child slot is a sequence, e.g. the branches of an `if`
+pub fn walk_children<V: Visitor + ?Sized>(visitor: &mut V, node: &TreeNode) {
+    for child in node.child.iter() {
+        visitor.visit_sequence(child.as_deref());
+    }
+}
+
+/// Like [`Visitor`], for passes that rewrite the tree in place.
+pub trait VisitorMut {
+    fn visit_sequence_mut(&mut self, node: Option<&mut TreeNode>) {
+        walk_sequence_mut(self, node)
+    }
+
+    fn visit_node_mut(&mut self, node: &mut TreeNode) {
+        walk_node_mut(self, node)
+    }
+
+    fn visit_stmt_mut(&mut self, node: &mut TreeNode) {
+        walk_children_mut(self, node)
+    }
+
+    fn visit_expr_mut(&mut self, node: &mut TreeNode) {
+        walk_children_mut(self, node)
+    }
+}
+
+pub fn walk_sequence_mut<V: VisitorMut + ?Sized>(visitor: &mut V, mut node: Option<&mut TreeNode>) {
+    while let Some(t) = node {
+        visitor.visit_node_mut(t);
+        node = t.sibling.as_deref_mut();
+    }
+}
+
+pub fn walk_node_mut<V: VisitorMut + ?Sized>(visitor: &mut V, node: &mut TreeNode) {
+    match node.kind {
+        Kind::Statement(_) => visitor.visit_stmt_mut(node),
+        Kind::Expression(_) => visitor.visit_expr_mut(node),
+    }
+}
+
+pub fn walk_children_mut<V: VisitorMut + ?Sized>(visitor: &mut V, node: &mut TreeNode) {
+    for child in node.child.iter_mut() {
+        visitor.visit_sequence_mut(child.as_deref_mut());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ast::visit::{walk_children, walk_children_mut, Visitor, VisitorMut};
+    use crate::ast::{Attr, TreeNode};
+    use crate::driver::scan;
+    use crate::parser::Parser;
+    use anyhow::Result;
+
+    // records the nodes in preorder
+    struct Names(Vec<String>);
+
+    impl Visitor for Names {
+        fn visit_stmt(&mut self, node: &TreeNode) {
+            self.0.push(format!("{:?}", node.kind));
+            walk_children(self, node)
+        }
+
+        fn visit_expr(&mut self, node: &TreeNode) {
+            self.0.push(node.attr.to_string());
+            walk_children(self, node)
+        }
+    }
+
+    // renames every variable to upper case
+    struct Upper;
+
+    impl VisitorMut for Upper {
+        fn visit_expr_mut(&mut self, node: &mut TreeNode) {
+            if let Attr::Name(name) = &node.attr {
+                node.attr = Attr::Name(name.to_uppercase());
+            }
+            walk_children_mut(self, node)
+        }
+    }
+
+    #[test]
+    fn test_visit() -> Result<()> {
+        let mut tree = Parser::new(scan("read x; if x < 1 then write x + 2 end")).parse()?;
+        let mut names = Names(vec![]);
+        names.visit_sequence(Some(&tree));
+        assert_eq!(
+            names.0,
+            vec![
+                "Statement(ReadK)",
+                "Statement(IfK)",
+                "<",
+                "x",
+                "1",
+                "Statement(WriteK)",
+                "+",
+                "x",
+                "2"
+            ]
+        );
+
+        Upper.visit_sequence_mut(Some(&mut tree));
+        let mut names = Names(vec![]);
+        names.visit_node(tree.sibling.as_ref().unwrap());
+        assert_eq!(names.0[2], "X");
+        Ok(())
+    }
+}
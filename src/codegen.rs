@@ -0,0 +1,386 @@
+use crate::ast::{Attr, ExpressionKind, Kind, StatementKind, TreeNode};
+use crate::symtable::SymTable;
+use crate::token::Token;
+use anyhow::Result;
+
+// program counter
+pub const PC: i32 = 7;
+// memory pointer: points to the top of memory, used for temporary storage
+pub const MP: i32 = 6;
+// global pointer: points to the bottom of memory, where the variables live
+pub const GP: i32 = 5;
+// accumulators
+pub const AC: i32 = 0;
+pub const AC1: i32 = 1;
+// scratch register for the quotient of `%`
+pub const AC2: i32 = 2;
+
+/// Generates TM assembly for a type checked syntax tree, following the
+/// `code.c`/`cgen.c` backend of Louden's TINY compiler.
+///
+/// Instructions are listed in emission order, so backpatched jumps show up
+/// after the code they skip; every instruction carries its own location.
+pub struct CodeGenerator<'a> {
+    sym_table: &'a SymTable,
+    // location of the next instruction to emit
+    emit_loc: i32,
+    // highest location emitted so far, for emit_skip/emit_backup/emit_restore
+    high_emit_loc: i32,
+    // next free temporary, as an offset from MP
+    tmp_offset: i32,
+    code: String,
+}
+
+impl<'a> CodeGenerator<'a> {
+    pub fn new(sym_table: &'a SymTable) -> Self {
+        Self {
+            sym_table,
+            emit_loc: 0,
+            high_emit_loc: 0,
+            tmp_offset: 0,
+            code: String::new(),
+        }
+    }
+
+    pub fn generate(mut self, tree: &TreeNode) -> Result<String> {
+        self.emit_comment("TINY Compilation to TM Code");
+        self.emit_comment("Standard prelude:");
+        self.emit_rm("LD", MP, 0, AC, "load maxaddress from location 0");
+        self.emit_rm("ST", AC, 0, AC, "clear location 0");
+        self.emit_comment("End of standard prelude.");
+        self.gen_sequence(Some(tree))?;
+        self.emit_comment("End of execution.");
+        self.emit_ro("HALT", 0, 0, 0, "");
+        Ok(self.code)
+    }
+
+    fn gen_sequence(&mut self, mut node: Option<&TreeNode>) -> Result<()> {
+        while let Some(t) = node {
+            match &t.kind {
+                Kind::Statement(stmt) => self.gen_stmt(stmt, t)?,
+                Kind::Expression(expr) => self.gen_expr(expr, t)?,
+            }
+            node = t.sibling.as_deref();
+        }
+        Ok(())
+    }
+
+    fn gen_child(&mut self, node: &TreeNode, idx: usize) -> Result<()> {
+        self.gen_sequence(node.child[idx].as_deref())
+    }
+
+    fn gen_stmt(&mut self, stmt: &StatementKind, node: &TreeNode) -> Result<()> {
+        match stmt {
+            StatementKind::IfK => {
+                self.emit_comment("-> if");
+                self.gen_child(node, 0)?;
+                let saved_loc1 = self.emit_skip(1);
+                self.emit_comment("if: jump to else belongs here");
+                self.gen_child(node, 1)?;
+                let saved_loc2 = self.emit_skip(1);
+                self.emit_comment("if: jump to end belongs here");
+                let current_loc = self.emit_skip(0);
+                self.emit_backup(saved_loc1);
+                self.emit_rm_abs("JEQ", AC, current_loc, "if: jmp to else");
+                self.emit_restore();
+                self.gen_child(node, 2)?;
+                let current_loc = self.emit_skip(0);
+                self.emit_backup(saved_loc2);
+                self.emit_rm_abs("LDA", PC, current_loc, "jmp to end");
+                self.emit_restore();
+                self.emit_comment("<- if");
+            }
+            StatementKind::RepeatK => {
+                self.emit_comment("-> repeat");
+                let saved_loc1 = self.emit_skip(0);
+                self.emit_comment("repeat: jump after body comes back here");
+                self.gen_child(node, 0)?;
+                self.gen_child(node, 1)?;
+                self.emit_rm_abs("JEQ", AC, saved_loc1, "repeat: jmp back to body");
+                self.emit_comment("<- repeat");
+            }
+            StatementKind::WhileK => {
+                self.emit_comment("-> while");
+                let saved_loc1 = self.emit_skip(0);
+                self.emit_comment("while: jump after body comes back here");
+                self.gen_child(node, 0)?;
+                let saved_loc2 = self.emit_skip(1);
+                self.emit_comment("while: jump to end belongs here");
+                self.gen_child(node, 1)?;
+                self.emit_rm_abs("LDA", PC, saved_loc1, "while: jmp back to test");
+                let current_loc = self.emit_skip(0);
+                self.emit_backup(saved_loc2);
+                self.emit_rm_abs("JEQ", AC, current_loc, "while: jmp to end");
+                self.emit_restore();
+                self.emit_comment("<- while");
+            }
+            StatementKind::ForK => {
+                self.emit_comment("-> for");
+                let init = node.child[0]
+                    .as_deref()
+                    .ok_or_else(|| anyhow::format_err!("missing loop variable in {}", node))?;
+                let loc = self.lookup(init)?;
+                self.gen_child(node, 0)?;
+                // the bound and the step are evaluated once, before the loop
+                self.gen_child(node, 1)?;
+                let bound = self.tmp_offset;
+                self.emit_rm("ST", AC, bound, MP, "for: save bound");
+                self.tmp_offset -= 1;
+                if node.child[2].is_some() {
+                    self.gen_child(node, 2)?;
+                } else {
+                    self.emit_rm("LDC", AC, 1, 0, "for: default step");
+                }
+                let step = self.tmp_offset;
+                self.emit_rm("ST", AC, step, MP, "for: save step");
+                self.tmp_offset -= 1;
+                // the loop ends once the counter is past the bound, or when
+                // stepping it does not move it on, as when it wraps around
+                let (exit_jump, step_op, stuck_jump) = match node.attr {
+                    Attr::Op(Token::Downto) => ("JLT", "SUB", "JLE"),
+                    _ => ("JGT", "ADD", "JGE"),
+                };
+                let saved_loc1 = self.emit_skip(0);
+                self.emit_comment("for: jump after body comes back here");
+                self.emit_rm("LD", AC1, loc, GP, "for: load counter");
+                self.emit_rm("LD", AC, bound, MP, "for: load bound");
+                self.emit_difference_sign("for: compare counter to bound");
+                let saved_loc2 = self.emit_skip(1);
+                self.emit_comment("for: jump to end belongs here");
+                self.gen_child(node, 3)?;
+                self.emit_rm("LD", AC1, loc, GP, "for: load counter");
+                self.emit_rm("LD", AC, step, MP, "for: load step");
+                self.emit_ro(step_op, AC, AC1, AC, "for: step counter");
+                self.emit_rm("ST", AC, loc, GP, "for: store counter");
+                self.emit_difference_sign("for: compare old counter to new");
+                let saved_loc3 = self.emit_skip(1);
+                self.emit_rm_abs("LDA", PC, saved_loc1, "for: jmp back to test");
+                let current_loc = self.emit_skip(0);
+                self.emit_backup(saved_loc2);
+                self.emit_rm_abs(exit_jump, AC, current_loc, "for: jmp to end");
+                self.emit_backup(saved_loc3);
+                self.emit_rm_abs(stuck_jump, AC, current_loc, "for: jmp to end");
+                self.emit_restore();
+                self.tmp_offset += 2;
+                self.emit_comment("<- for");
+            }
+            StatementKind::AssignK => {
+                self.emit_comment("-> assign");
+                self.gen_child(node, 0)?;
+                let loc = self.lookup(node)?;
+                self.emit_rm("ST", AC, loc, GP, "assign: store value");
+                self.emit_comment("<- assign");
+            }
+            StatementKind::ReadK => {
+                self.emit_ro("IN", AC, 0, 0, "read integer value");
+                let loc = self.lookup(node)?;
+                self.emit_rm("ST", AC, loc, GP, "read: store value");
+            }
+            StatementKind::WriteK => {
+                self.gen_child(node, 0)?;
+                self.emit_ro("OUT", AC, 0, 0, "write ac");
+            }
+        }
+        Ok(())
+    }
+
+    fn gen_expr(&mut self, expr: &ExpressionKind, node: &TreeNode) -> Result<()> {
+        match expr {
+            ExpressionKind::ConstK => {
+                self.emit_comment("-> Const");
+                match node.attr {
+                    Attr::Val(val) => self.emit_rm("LDC", AC, val, 0, "load const"),
+                    // booleans are stored as 1 and 0, like comparison results
+                    Attr::Bool(b) => self.emit_rm("LDC", AC, b as i32, 0, "load const"),
+                    _ => {}
+                }
+                self.emit_comment("<- Const");
+            }
+            ExpressionKind::IdK => {
+                self.emit_comment("-> Id");
+                let loc = self.lookup(node)?;
+                self.emit_rm("LD", AC, loc, GP, "load id value");
+                self.emit_comment("<- Id");
+            }
+            ExpressionKind::Opk if node.is_unary() => {
+                self.emit_comment("-> Op");
+                self.gen_child(node, 0)?;
+                match &node.attr {
+                    Attr::Op(Token::Not) => self.emit_test("JEQ", "op not"),
+                    Attr::Op(Token::Minus) => {
+                        self.emit_rm("LDC", AC1, 0, 0, "op unary -: load 0");
+                        self.emit_ro("SUB", AC, AC1, AC, "op unary -");
+                    }
+                    Attr::Op(Token::Plus) => {}
+                    attr => return Err(anyhow::format_err!("unknown unary operator {}", attr)),
+                }
+                self.emit_comment("<- Op");
+            }
+            ExpressionKind::Opk if matches!(node.attr, Attr::Op(Token::And | Token::Or)) => {
+                // the right operand is skipped once the left one decides
+                // the result, which is then still in ac
+                self.emit_comment("-> Op");
+                self.gen_child(node, 0)?;
+                let saved_loc = self.emit_skip(1);
+                self.gen_child(node, 1)?;
+                let current_loc = self.emit_skip(0);
+                self.emit_backup(saved_loc);
+                if node.attr == Attr::Op(Token::And) {
+                    self.emit_rm_abs("JEQ", AC, current_loc, "op and: skip right if false");
+                } else {
+                    self.emit_rm_abs("JNE", AC, current_loc, "op or: skip right if true");
+                }
+                self.emit_restore();
+                self.emit_comment("<- Op");
+            }
+            ExpressionKind::Opk => {
+                self.emit_comment("-> Op");
+                self.gen_child(node, 0)?;
+                self.emit_rm("ST", AC, self.tmp_offset, MP, "op: push left");
+                self.tmp_offset -= 1;
+                self.gen_child(node, 1)?;
+                self.tmp_offset += 1;
+                self.emit_rm("LD", AC1, self.tmp_offset, MP, "op: load left");
+                match &node.attr {
+                    Attr::Op(Token::Plus) => self.emit_ro("ADD", AC, AC1, AC, "op +"),
+                    Attr::Op(Token::Minus) => self.emit_ro("SUB", AC, AC1, AC, "op -"),
+                    Attr::Op(Token::Times) => self.emit_ro("MUL", AC, AC1, AC, "op *"),
+                    Attr::Op(Token::Over) => self.emit_ro("DIV", AC, AC1, AC, "op /"),
+                    // a % b = a - (a / b) * b, which has the sign of a
+                    Attr::Op(Token::Mod) => {
+                        self.emit_ro("DIV", AC2, AC1, AC, "op %: quotient");
+                        self.emit_ro("MUL", AC2, AC2, AC, "op %: quotient * divisor");
+                        self.emit_ro("SUB", AC, AC1, AC2, "op %");
+                    }
+                    Attr::Op(Token::Lt) => self.emit_compare("JLT", "op <"),
+                    Attr::Op(Token::Le) => self.emit_compare("JLE", "op <="),
+                    Attr::Op(Token::Gt) => self.emit_compare("JGT", "op >"),
+                    Attr::Op(Token::Ge) => self.emit_compare("JGE", "op >="),
+                    Attr::Op(Token::Eq) => self.emit_compare("JEQ", "op =="),
+                    Attr::Op(Token::Ne) => self.emit_compare("JNE", "op <>"),
+                    attr => return Err(anyhow::format_err!("unknown operator {}", attr)),
+                }
+                self.emit_comment("<- Op");
+            }
+        }
+        Ok(())
+    }
+
+    // ac := (ac1 - ac) <jump condition> 0 ? 1 : 0
+    fn emit_compare(&mut self, jump: &str, comment: &str) {
+        self.emit_difference_sign(comment);
+        self.emit_test(jump, "br if true");
+    }
+
+    // ac := a value with the sign of ac1 - ac. The subtraction could
+    // overflow when the signs of the operands differ, but then the sign of
+    // ac1 decides, so ac is set to 1 or -1 instead.
+    fn emit_difference_sign(&mut self, comment: &str) {
+        self.emit_rm("JLT", AC1, 3, PC, "left < 0");
+        self.emit_rm("JGE", AC, 5, PC, "both >= 0: subtract");
+        self.emit_rm("LDC", AC, 1, 0, "left >= 0 > right");
+        self.emit_rm("LDA", PC, 4, PC, "unconditional jmp");
+        self.emit_rm("JLT", AC, 2, PC, "both < 0: subtract");
+        self.emit_rm("LDC", AC, -1, 0, "left < 0 <= right");
+        self.emit_rm("LDA", PC, 1, PC, "unconditional jmp");
+        self.emit_ro("SUB", AC, AC1, AC, comment);
+    }
+
+    // ac := ac <jump condition> 0 ? 1 : 0
+    fn emit_test(&mut self, jump: &str, comment: &str) {
+        self.emit_rm(jump, AC, 2, PC, comment);
+        self.emit_rm("LDC", AC, 0, AC, "false case");
+        self.emit_rm("LDA", PC, 1, PC, "unconditional jmp");
+        self.emit_rm("LDC", AC, 1, AC, "true case");
+    }
+
+    fn lookup(&self, node: &TreeNode) -> Result<i32> {
+        if let Attr::Name(name) = &node.attr {
+            if let Some(loc) = self.sym_table.st_lookup(name) {
+                return Ok(loc);
+            }
+        }
+        Err(anyhow::format_err!(
+            "no memory location for `{}` in the symbol table",
+            node.attr
+        ))
+    }
+
+    fn emit_comment(&mut self, comment: &str) {
+        self.code.push_str(&format!("* {}\n", comment));
+    }
+
+    // register-only instruction: op r,s,t
+    fn emit_ro(&mut self, op: &str, r: i32, s: i32, t: i32, comment: &str) {
+        let line = format!("{:3}:  {:>5}  {},{},{} ", self.emit_loc, op, r, s, t);
+        self.emit_line(line, comment);
+    }
+
+    // register-to-memory instruction: op r,d(s)
+    fn emit_rm(&mut self, op: &str, r: i32, d: i32, s: i32, comment: &str) {
+        let line = format!("{:3}:  {:>5}  {},{}({}) ", self.emit_loc, op, r, d, s);
+        self.emit_line(line, comment);
+    }
+
+    // register-to-memory instruction with an absolute target, made pc-relative
+    fn emit_rm_abs(&mut self, op: &str, r: i32, a: i32, comment: &str) {
+        let d = a - (self.emit_loc + 1);
+        self.emit_rm(op, r, d, PC, comment);
+    }
+
+    fn emit_line(&mut self, line: String, comment: &str) {
+        self.code.push_str(line.trim_end());
+        if !comment.is_empty() {
+            self.code.push_str(&format!(" \t{}", comment));
+        }
+        self.code.push('\n');
+        self.emit_loc += 1;
+        if self.high_emit_loc < self.emit_loc {
+            self.high_emit_loc = self.emit_loc;
+        }
+    }
+
+    // reserves `how_many` locations for later backpatching, returns the first
+    fn emit_skip(&mut self, how_many: i32) -> i32 {
+        let i = self.emit_loc;
+        self.emit_loc += how_many;
+        if self.high_emit_loc < self.emit_loc {
+            self.high_emit_loc = self.emit_loc;
+        }
+        i
+    }
+
+    fn emit_backup(&mut self, loc: i32) {
+        assert!(loc <= self.high_emit_loc, "BUG in emit_backup");
+        self.emit_loc = loc;
+    }
+
+    fn emit_restore(&mut self) {
+        self.emit_loc = self.high_emit_loc;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::codegen::CodeGenerator;
+    use crate::driver::compile;
+    use anyhow::Result;
+
+    #[test]
+    fn test_generate() -> Result<()> {
+        let compilation = compile("read x; if 0 < x then write x end")?;
+        let code = CodeGenerator::new(&compilation.sym_table).generate(&compilation.tree)?;
+        println!("{}", code);
+        let instructions: Vec<&str> = code.lines().filter(|l| !l.starts_with('*')).collect();
+        assert_eq!(
+            instructions[0],
+            "  0:     LD  6,0(0) \tload maxaddress from location 0"
+        );
+        assert_eq!(instructions[2], "  2:     IN  0,0,0 \tread integer value");
+        assert_eq!(instructions[3], "  3:     ST  0,0(5) \tread: store value");
+        // the jump to the else part is backpatched after the then part
+        assert!(code.contains(" 20:    JEQ  0,3(7) \tif: jmp to else"));
+        assert_eq!(instructions.last(), Some(&" 24:   HALT  0,0,0"));
+        Ok(())
+    }
+}
@@ -0,0 +1,246 @@
+use crate::diagnostic::Diagnostic;
+use crate::parser::{Event, Parser};
+use crate::scanner::Scanner;
+use crate::token::{Span, SpannedToken, Token, TriviaKind};
+use std::fmt::{Display, Formatter};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SyntaxKind {
+    Program,
+    IfStmt,
+    RepeatStmt,
+    WhileStmt,
+    ForStmt,
+    AssignStmt,
+    ReadStmt,
+    WriteStmt,
+    BinaryExpr,
+    UnaryExpr,
+    ParenExpr,
+    Literal,
+    Name,
+    // tokens skipped while recovering from a syntax error
+    Error,
+}
+
+/// Whitespace or a comment, with its text.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TriviaPiece {
+    pub kind: TriviaKind,
+    pub text: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SyntaxToken {
+    pub token: Token,
+    pub span: Span,
+    pub text: String,
+    /// the whitespace and comments between this token and the previous one
+    pub leading: Vec<TriviaPiece>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SyntaxElement {
+    Node(SyntaxNode),
+    Token(SyntaxToken),
+}
+
+/// A node of the concrete syntax tree. Unlike `TreeNode` it keeps every
+/// token, including keywords, punctuation, comments and whitespace, so
+/// printing it with `Display` reproduces the source byte for byte.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SyntaxNode {
+    pub kind: SyntaxKind,
+    pub children: Vec<SyntaxElement>,
+}
+
+impl SyntaxNode {
+    fn new(kind: SyntaxKind) -> Self {
+        Self {
+            kind,
+            children: vec![],
+        }
+    }
+
+    pub fn child_nodes(&self) -> impl Iterator<Item = &SyntaxNode> {
+        self.children.iter().filter_map(|child| match child {
+            SyntaxElement::Node(node) => Some(node),
+            SyntaxElement::Token(_) => None,
+        })
+    }
+
+    pub fn tokens(&self) -> impl Iterator<Item = &SyntaxToken> {
+        self.children.iter().filter_map(|child| match child {
+            SyntaxElement::Token(token) => Some(token),
+            SyntaxElement::Node(_) => None,
+        })
+    }
+
+    // the indented outline of the tree, one node or token per line
+    fn print_tree(&self, f: &mut Formatter<'_>, indent: usize) -> std::fmt::Result {
+        writeln!(f, "{}{:?}", " ".repeat(indent), self.kind)?;
+        for child in self.children.iter() {
+            match child {
+                SyntaxElement::Node(node) => node.print_tree(f, indent + 2)?,
+                SyntaxElement::Token(token) => {
+                    writeln!(f, "{}{:?}", " ".repeat(indent + 2), token.text)?
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Wraps the node so that `Display` prints its outline instead of its text.
+    pub fn outline(&self) -> Outline<'_> {
+        Outline(self)
+    }
+}
+
+impl Display for SyntaxNode {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for child in self.children.iter() {
+            match child {
+                SyntaxElement::Node(node) => write!(f, "{}", node)?,
+                SyntaxElement::Token(token) => {
+                    for trivia in token.leading.iter() {
+                        write!(f, "{}", trivia.text)?;
+                    }
+                    write!(f, "{}", token.text)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+pub struct Outline<'a>(&'a SyntaxNode);
+
+impl Display for Outline<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        self.0.print_tree(f, 0)
+    }
+}
+
+/// Parses `source` into a concrete syntax tree, recovering from errors like
+/// `Parser::parse_recovering`. The tree is complete even for invalid
+/// programs: tokens the parser skipped end up in `Error` nodes, and the
+/// trivia at the end of the file belongs to the final end of file token.
+pub fn parse(source: &str) -> (SyntaxNode, Vec<Diagnostic>) {
+    let mut scanner = Scanner::new(source).with_trivia();
+    let mut tokens = vec![];
+    loop {
+        let token = scanner.get_spanned_token();
+        let leading = scanner
+            .trivia()
+            .iter()
+            .map(|trivia| TriviaPiece {
+                kind: trivia.kind,
+                text: source[trivia.span.start..trivia.span.end].to_string(),
+            })
+            .collect();
+        let done = token.token == Token::EndFile;
+        tokens.push(SyntaxToken {
+            text: source[token.span.start..token.span.end].to_string(),
+            token: token.token,
+            span: token.span,
+            leading,
+        });
+        if done {
+            break;
+        }
+    }
+    let mut diagnostics = scanner.diagnostics().to_vec();
+
+    let spanned = tokens
+        .iter()
+        .map(|t| SpannedToken::new(t.token.clone(), t.span))
+        .collect();
+    let mut parser = Parser::new(spanned);
+    let (_, syntax_errors) = parser.parse_recovering();
+    diagnostics.extend(syntax_errors);
+    (build(parser.events(), tokens), diagnostics)
+}
+
+fn build(events: &[Event], tokens: Vec<SyntaxToken>) -> SyntaxNode {
+    let mut tokens = tokens.into_iter().enumerate().peekable();
+    let mut stack: Vec<SyntaxNode> = vec![];
+    let mut root = SyntaxNode::new(SyntaxKind::Program);
+    for event in events {
+        match *event {
+            Event::Start(kind) => stack.push(SyntaxNode::new(kind)),
+            Event::Finish => {
+                let node = stack.pop().expect("unbalanced parse events");
+                match stack.last_mut() {
+                    Some(parent) => parent.children.push(SyntaxElement::Node(node)),
+                    None => root = node,
+                }
+            }
+            Event::Token(idx) => {
+                let parent = stack.last_mut().expect("token outside of a node");
+                while let Some((_, token)) = tokens.next_if(|(i, _)| *i <= idx) {
+                    parent.children.push(SyntaxElement::Token(token));
+                }
+            }
+        }
+    }
+    // the end of file token carries the trailing trivia
+    for (_, token) in tokens {
+        root.children.push(SyntaxElement::Token(token));
+    }
+    root
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cst::{parse, SyntaxKind};
+
+    #[test]
+    fn test_lossless() {
+        let sources = [
+            "{ Sample program }\nread x; { input }\nif 0 < x then\n  write (x + 1) * 2 { twice }\nend\n\n{ done }\n",
+            "x := 1 @ 2;\n  read 5; end until\twrite (1",
+            "  { unterminated",
+            "",
+        ];
+        for source in sources {
+            let (tree, _) = parse(source);
+            assert_eq!(tree.to_string(), source);
+        }
+    }
+
+    #[test]
+    fn test_cst_shape() {
+        let (tree, diagnostics) = parse("read x; { c }\nwrite 1 + 2 * x");
+        assert!(diagnostics.is_empty());
+        assert_eq!(
+            tree.outline().to_string(),
+            r#"Program
+  ReadStmt
+    "read"
+    "x"
+  ";"
+  WriteStmt
+    "write"
+    BinaryExpr
+      Literal
+        "1"
+      "+"
+      BinaryExpr
+        Literal
+          "2"
+        "*"
+        Name
+          "x"
+  ""
+"#
+        );
+        let write = tree.child_nodes().nth(1).unwrap();
+        let write_token = write.tokens().next().unwrap();
+        assert_eq!(write_token.leading.len(), 3);
+        assert_eq!(write_token.leading[1].text, "{ c }");
+
+        let (tree, _) = parse("x := ; y := 1");
+        let assign = tree.child_nodes().next().unwrap();
+        assert_eq!(assign.kind, SyntaxKind::AssignStmt);
+    }
+}
@@ -0,0 +1,223 @@
+use crate::token::Span;
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+impl Display for Severity {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Severity::Error => write!(f, "error"),
+            Severity::Warning => write!(f, "warning"),
+        }
+    }
+}
+
+/// A message attached to a region of the source.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Label {
+    pub span: Span,
+    pub message: String,
+}
+
+/// A problem found in the source, with enough structure to be rendered
+/// like rustc does: the offending line, a caret underline and notes.
+///
+/// `Diagnostic` implements `Error`, so the passes can return it through
+/// `anyhow::Result` and the driver can get it back with `downcast_ref`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub code: &'static str,
+    pub message: String,
+    /// the primary span, underlined with `^`
+    pub span: Span,
+    /// text printed next to the primary underline
+    pub label: String,
+    /// secondary spans, underlined with `-`
+    pub labels: Vec<Label>,
+    pub notes: Vec<String>,
+}
+
+impl Diagnostic {
+    pub fn new(severity: Severity, code: &'static str, message: String, span: Span) -> Self {
+        Self {
+            severity,
+            code,
+            message,
+            span,
+            label: String::new(),
+            labels: vec![],
+            notes: vec![],
+        }
+    }
+
+    pub fn error(code: &'static str, message: String, span: Span) -> Self {
+        Self::new(Severity::Error, code, message, span)
+    }
+
+    pub fn warning(code: &'static str, message: String, span: Span) -> Self {
+        Self::new(Severity::Warning, code, message, span)
+    }
+
+    pub fn with_label(mut self, label: &str) -> Self {
+        self.label = label.into();
+        self
+    }
+
+    pub fn with_secondary(mut self, span: Span, message: &str) -> Self {
+        self.labels.push(Label {
+            span,
+            message: message.into(),
+        });
+        self
+    }
+
+    pub fn with_note(mut self, note: &str) -> Self {
+        self.notes.push(note.into());
+        self
+    }
+
+    pub fn is_error(&self) -> bool {
+        self.severity == Severity::Error
+    }
+
+    /// Renders the diagnostic against the source text it was produced for:
+    ///
+    /// ```text
+    /// error[E0201]: if-condition must be boolean, found integer
+    ///  --> fact.tny:2:4
+    ///   |
+    /// 2 | if x then
+    ///   |    ^ expected boolean
+    /// ```
+    pub fn render(&self, source: &str, file_name: &str) -> String {
+        let mut underlines: Vec<(Span, char, &str)> = vec![(self.span, '^', &self.label)];
+        for label in self.labels.iter() {
+            underlines.push((label.span, '-', &label.message));
+        }
+        underlines.sort_by_key(|(span, _, _)| (span.line, span.col));
+
+        let max_line = underlines.iter().map(|(span, _, _)| span.line).max();
+        let width = max_line.unwrap_or(0).to_string().len();
+        let gutter = " ".repeat(width);
+
+        let mut out = format!("{}\n", self);
+        out.push_str(&format!(
+            "{}--> {}:{}:{}\n",
+            gutter, file_name, self.span.line, self.span.col
+        ));
+        out.push_str(&format!("{} |\n", gutter));
+        let mut last_line = 0;
+        for (span, mark, message) in underlines {
+            let Some(text) = source.lines().nth(span.line.saturating_sub(1)) else {
+                continue;
+            };
+            if span.line != last_line {
+                out.push_str(&format!("{:>width$} | {}\n", span.line, text));
+                last_line = span.line;
+            }
+            let (indent, len) = underline(text, span, source);
+            let marks = mark.to_string().repeat(len);
+            let line = format!("{} | {}{} {}", gutter, indent, marks, message);
+            out.push_str(line.trim_end());
+            out.push('\n');
+        }
+        for note in self.notes.iter() {
+            out.push_str(&format!("{} = note: {}\n", gutter, note));
+        }
+        out
+    }
+}
+
+// whitespace up to the span's column (keeping tabs) and the underline length,
+// which stops at the end of the span's first line
+fn underline(text: &str, span: Span, source: &str) -> (String, usize) {
+    let indent: String = text
+        .chars()
+        .take(span.col.saturating_sub(1))
+        .map(|c| if c == '\t' { '\t' } else { ' ' })
+        .collect();
+    let spanned = source.get(span.start..span.end).unwrap_or("");
+    let len = spanned.lines().next().map_or(0, |l| l.chars().count());
+    (indent, len.max(1))
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}[{}]: {}", self.severity, self.code, self.message)
+    }
+}
+
+impl Error for Diagnostic {}
+
+/// Every diagnostic a pass produced, for passes that keep going after the
+/// first error. Like `Diagnostic`, it can travel through `anyhow::Result`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Diagnostics(pub Vec<Diagnostic>);
+
+impl Diagnostics {
+    pub fn error_count(&self) -> usize {
+        self.0.iter().filter(|d| d.is_error()).count()
+    }
+}
+
+impl Display for Diagnostics {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for (i, diagnostic) in self.0.iter().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+            write!(f, "{}", diagnostic)?;
+        }
+        Ok(())
+    }
+}
+
+impl Error for Diagnostics {}
+
+#[cfg(test)]
+mod tests {
+    use crate::diagnostic::Diagnostic;
+    use crate::token::Span;
+
+    #[test]
+    fn test_render() {
+        let source = "read x;\nif x then\n\twrite 1 + y\nend";
+        let diagnostic = Diagnostic::error(
+            "E0201",
+            "if-condition must be boolean, found integer".into(),
+            Span::new(11, 12, 2, 4),
+        )
+        .with_label("expected boolean")
+        .with_secondary(Span::new(8, 10, 2, 1), "in this if-statement")
+        .with_note("conditions are built with `<` or `=`");
+        assert_eq!(
+            diagnostic.render(source, "test.tny"),
+            "error[E0201]: if-condition must be boolean, found integer
+ --> test.tny:2:4
+  |
+2 | if x then
+  | -- in this if-statement
+  |    ^ expected boolean
+  = note: conditions are built with `<` or `=`
+"
+        );
+
+        let diagnostic =
+            Diagnostic::warning("W0001", "unused".into(), Span::new(27, 30, 3, 10)).with_label("");
+        assert_eq!(
+            diagnostic.render(source, "test.tny"),
+            "warning[W0001]: unused
+ --> test.tny:3:10
+  |
+3 | \twrite 1 + y
+  | \t        ^^^
+"
+        );
+    }
+}
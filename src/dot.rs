@@ -0,0 +1,201 @@
+use crate::ast::{Attr, ExpressionKind, ExpressionType, Kind, StatementKind, TreeNode};
+use crate::token::Token;
+use std::fmt::{Display, Formatter};
+
+type Attrs = Vec<(String, String)>;
+
+/// A directed graph in Graphviz DOT form. Nodes and edges carry arbitrary
+/// attributes, so the same builder serves syntax trees and control-flow
+/// graphs alike.
+#[derive(Clone, Debug, Default)]
+pub struct Graph {
+    name: String,
+    // attributes applied to every node
+    node_defaults: Attrs,
+    nodes: Vec<Attrs>,
+    edges: Vec<(usize, usize, Attrs)>,
+}
+
+impl Graph {
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.into(),
+            ..Self::default()
+        }
+    }
+
+    pub fn with_node_defaults(mut self, attrs: &[(&str, &str)]) -> Self {
+        self.node_defaults = owned(attrs);
+        self
+    }
+
+    /// Adds a node and returns its id, for use in `add_edge`.
+    pub fn add_node(&mut self, label: &str, attrs: &[(&str, &str)]) -> usize {
+        let mut attrs = owned(attrs);
+        attrs.insert(0, ("label".into(), label.into()));
+        self.nodes.push(attrs);
+        self.nodes.len() - 1
+    }
+
+    pub fn add_edge(&mut self, from: usize, to: usize, attrs: &[(&str, &str)]) {
+        self.edges.push((from, to, owned(attrs)));
+    }
+}
+
+fn owned(attrs: &[(&str, &str)]) -> Attrs {
+    attrs
+        .iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect()
+}
+
+// `[k="v", ...]`, or nothing when there are no attributes
+fn attr_list(attrs: &[(String, String)]) -> String {
+    if attrs.is_empty() {
+        return String::new();
+    }
+    let attrs: Vec<String> = attrs
+        .iter()
+        .map(|(k, v)| format!("{}={}", k, quote(v)))
+        .collect();
+    format!(" [{}]", attrs.join(", "))
+}
+
+fn quote(text: &str) -> String {
+    let mut out = String::from("\"");
+    for c in text.chars() {
+        match c {
+            '"' | '\\' => {
+                out.push('\\');
+                out.push(c);
+            }
+            '\n' => out.push_str("\\n"),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+impl Display for Graph {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "digraph {} {{", quote(&self.name))?;
+        if !self.node_defaults.is_empty() {
+            writeln!(f, "  node{};", attr_list(&self.node_defaults))?;
+        }
+        for (id, attrs) in self.nodes.iter().enumerate() {
+            writeln!(f, "  n{}{};", id, attr_list(attrs))?;
+        }
+        for (from, to, attrs) in self.edges.iter() {
+            writeln!(f, "  n{} -> n{}{};", from, to, attr_list(attrs))?;
+        }
+        writeln!(f, "}}")
+    }
+}
+
+/// Draws the syntax tree starting at `tree`. Statements are boxes and
+/// expressions ellipses, labelled with their type once the tree has been
+/// type checked. Child edges are labelled with the part of the statement
+/// they lead to; dashed edges link a statement to the next one.
+pub fn tree_to_dot(tree: &TreeNode) -> String {
+    let mut graph = Graph::new("ast").with_node_defaults(&[("fontname", "monospace")]);
+    add_sequence(&mut graph, tree);
+    graph.to_string()
+}
+
+// adds `node` and its siblings, returning the id of the first one
+fn add_sequence(graph: &mut Graph, node: &TreeNode) -> usize {
+    let first = add_tree(graph, node);
+    let mut prev = first;
+    let mut next = node.sibling.as_deref();
+    while let Some(t) = next {
+        let id = add_tree(graph, t);
+        graph.add_edge(prev, id, &[("style", "dashed")]);
+        prev = id;
+        next = t.sibling.as_deref();
+    }
+    first
+}
+
+fn add_tree(graph: &mut Graph, node: &TreeNode) -> usize {
+    let (label, shape, roles): (String, &str, &[&str]) = match &node.kind {
+        Kind::Statement(stmt) => match stmt {
+            StatementKind::IfK => ("if".into(), "box", &["cond", "then", "else"]),
+            StatementKind::RepeatK => ("repeat".into(), "box", &["body", "until"]),
+            StatementKind::WhileK => ("while".into(), "box", &["cond", "body"]),
+            StatementKind::ForK => {
+                let roles: &[&str] = &["init", "bound", "step", "body"];
+                match node.attr {
+                    Attr::Op(Token::Downto) => ("for downto".into(), "box", roles),
+                    _ => ("for to".into(), "box", roles),
+                }
+            }
+            StatementKind::AssignK => (format!("assign to {}", node.attr), "box", &[]),
+            StatementKind::ReadK => (format!("read {}", node.attr), "box", &[]),
+            StatementKind::WriteK => ("write".into(), "box", &[]),
+        },
+        Kind::Expression(expr) => {
+            let text = match expr {
+                ExpressionKind::Opk => match &node.attr {
+                    Attr::Op(op) => format!("op {}", op.text()),
+                    attr => format!("op {}", attr),
+                },
+                ExpressionKind::ConstK => format!("const {}", node.attr),
+                ExpressionKind::IdK => format!("id {}", node.attr),
+            };
+            match node.expression_type {
+                ExpressionType::Void => (text, "ellipse", &[]),
+                ref ty => (format!("{}\n{}", text, ty), "ellipse", &[]),
+            }
+        }
+    };
+    let id = graph.add_node(&label, &[("shape", shape)]);
+    for (i, child) in node.child.iter().enumerate() {
+        if let Some(child) = child {
+            let child_id = add_sequence(graph, child);
+            let attrs: &[(&str, &str)] = match roles.get(i) {
+                Some(role) => &[("label", role)],
+                None => &[],
+            };
+            graph.add_edge(id, child_id, attrs);
+        }
+    }
+    id
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::dot::{tree_to_dot, Graph};
+    use crate::driver::compile;
+    use anyhow::Result;
+
+    #[test]
+    fn test_graph() {
+        let mut graph = Graph::new("cfg");
+        let entry = graph.add_node("entry \"B0\"", &[]);
+        let exit = graph.add_node("exit", &[("shape", "doublecircle")]);
+        graph.add_edge(entry, exit, &[("label", "true")]);
+        assert_eq!(
+            graph.to_string(),
+            "digraph \"cfg\" {
+  n0 [label=\"entry \\\"B0\\\"\"];
+  n1 [label=\"exit\", shape=\"doublecircle\"];
+  n0 -> n1 [label=\"true\"];
+}
+"
+        );
+    }
+
+    #[test]
+    fn test_tree_to_dot() -> Result<()> {
+        let compilation = compile("read x; if x < 1 then write x end")?;
+        let dot = tree_to_dot(&compilation.tree);
+        assert!(dot.starts_with("digraph \"ast\" {\n  node [fontname=\"monospace\"];\n"));
+        assert!(dot.contains("  n0 [label=\"read x\", shape=\"box\"];\n"));
+        assert!(dot.contains("  n2 [label=\"op <\\nboolean\", shape=\"ellipse\"];\n"));
+        assert!(dot.contains("  n0 -> n1 [style=\"dashed\"];\n"));
+        assert!(dot.contains("  n1 -> n2 [label=\"cond\"];\n"));
+        assert!(dot.contains("  n1 -> n5 [label=\"then\"];\n"));
+        Ok(())
+    }
+}
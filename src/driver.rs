@@ -0,0 +1,301 @@
+use crate::analyzer::Analyzer;
+use crate::ast::serialize::{to_json, to_sexp};
+use crate::ast::TreeNode;
+use crate::codegen::CodeGenerator;
+use crate::diagnostic::{Diagnostic, Diagnostics};
+use crate::dot::tree_to_dot;
+use crate::parser::Parser;
+use crate::scanner::Scanner;
+use crate::symtable::SymTable;
+use crate::token::{SpannedToken, Token};
+use anyhow::Result;
+use std::io::{self, Write};
+use std::str::FromStr;
+
+/// The artifact the driver writes once the pipeline has finished.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Emit {
+    Tokens,
+    Ast,
+    Json,
+    Sexp,
+    Dot,
+    Tm,
+}
+
+impl FromStr for Emit {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "tokens" => Ok(Emit::Tokens),
+            "ast" => Ok(Emit::Ast),
+            "json" => Ok(Emit::Json),
+            "sexp" => Ok(Emit::Sexp),
+            "dot" => Ok(Emit::Dot),
+            "tm" => Ok(Emit::Tm),
+            _ => Err(anyhow::format_err!(
+                "unknown emit kind `{}` (expected tokens, ast, json, sexp, dot or tm)",
+                s
+            )),
+        }
+    }
+}
+
+/// Everything produced by a successful run of the front end.
+#[derive(Debug, Clone)]
+pub struct Compilation {
+    pub tokens: Vec<SpannedToken>,
+    pub tree: TreeNode,
+    pub sym_table: SymTable,
+    /// warnings that did not stop the compilation
+    pub warnings: Vec<Diagnostic>,
+    /// TM assembly for the whole program
+    pub code: String,
+}
+
+impl Compilation {
+    pub fn emit(&self, emit: Emit) -> String {
+        match emit {
+            Emit::Tokens => {
+                let mut out = String::new();
+                for token in self.tokens.iter() {
+                    out.push_str(&format!("{}: {}\n", token.span, token.token));
+                }
+                out
+            }
+            Emit::Ast => format!("{}", self.tree),
+            Emit::Json => format!("{}\n", to_json(&self.tree)),
+            Emit::Sexp => format!("{}\n", to_sexp(&self.tree)),
+            Emit::Dot => tree_to_dot(&self.tree),
+            Emit::Tm => self.code.clone(),
+        }
+    }
+}
+
+/// Per-phase listings, after the TraceScan/TraceParse/TraceAnalyze/TraceCode
+/// flags of Louden's original compiler.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TraceFlags {
+    /// every token with the line it was read on
+    pub scan: bool,
+    /// the syntax tree
+    pub parse: bool,
+    /// the symbol table and type checking progress
+    pub analyze: bool,
+    /// the generated TM code
+    pub code: bool,
+}
+
+impl TraceFlags {
+    pub fn all() -> Self {
+        Self {
+            scan: true,
+            parse: true,
+            analyze: true,
+            code: true,
+        }
+    }
+}
+
+pub fn scan(source: &str) -> Vec<SpannedToken> {
+    // writing to a sink cannot fail
+    scan_traced(source, false, &mut io::sink()).unwrap().0
+}
+
+fn scan_traced(
+    source: &str,
+    trace: bool,
+    out: &mut dyn Write,
+) -> Result<(Vec<SpannedToken>, Vec<Diagnostic>)> {
+    let mut scanner = Scanner::new(source);
+    let mut tokens = vec![];
+    loop {
+        let token = scanner.get_spanned_token();
+        if trace {
+            writeln!(out, "\t{}: {}", token.span.line, token.token)?;
+        }
+        let done = token.token == Token::EndFile;
+        tokens.push(token);
+        if done {
+            break;
+        }
+    }
+    Ok((tokens, scanner.diagnostics().to_vec()))
+}
+
+// scan -> parse -> build symbol table -> type check -> generate code
+pub fn compile(source: &str) -> Result<Compilation> {
+    compile_traced(source, TraceFlags::default(), &mut io::sink())
+}
+
+/// Runs the pipeline like [`compile`], writing the listing of every phase
+/// selected in `trace` to `out` as soon as that phase has finished.
+pub fn compile_traced(source: &str, trace: TraceFlags, out: &mut dyn Write) -> Result<Compilation> {
+    let (tokens, mut diagnostics) = scan_traced(source, trace.scan, out)?;
+
+    // lexical and syntax errors are reported together
+    let mut parser = Parser::new(tokens.clone());
+    let (tree, syntax_errors) = parser.parse_recovering();
+    diagnostics.extend(syntax_errors);
+    let tree = match tree {
+        Some(tree) if !diagnostics.iter().any(Diagnostic::is_error) => tree,
+        _ => return Err(Diagnostics(diagnostics).into()),
+    };
+    if trace.parse {
+        writeln!(out, "\nSyntax tree:")?;
+        write!(out, "{}", tree)?;
+    }
+
+    let mut root = Some(Box::new(tree));
+    let mut analyzer = Analyzer::new();
+    if trace.analyze {
+        writeln!(out, "\nBuilding Symbol Table...")?;
+    }
+    let sym_table = analyzer.build_symbol_table(&root);
+    if trace.analyze {
+        writeln!(out, "\nSymbol table:\n")?;
+        write!(out, "{}", sym_table)?;
+        writeln!(out, "\nChecking Types...")?;
+    }
+    let type_errors = Analyzer::type_check_all(&mut root);
+    if !type_errors.is_empty() {
+        return Err(Diagnostics(type_errors).into());
+    }
+    let mut warnings = Analyzer::check_assignments(&root);
+    warnings.extend(Analyzer::check_unused(&root, &sym_table));
+    warnings.sort_by_key(|warning| warning.span.start);
+    if trace.analyze {
+        writeln!(out, "\nType Checking Finished")?;
+    }
+
+    let tree = *root.unwrap();
+    let code = CodeGenerator::new(&sym_table).generate(&tree)?;
+    if trace.code {
+        writeln!(out, "\nGenerated code:")?;
+        write!(out, "{}", code)?;
+    }
+
+    Ok(Compilation {
+        tokens,
+        tree,
+        sym_table,
+        warnings,
+        code,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::diagnostic::Diagnostics;
+    use crate::driver::{compile, compile_traced, Emit, TraceFlags};
+    use anyhow::Result;
+
+    const FACT: &str = "{ Sample program
+  in TINY language -
+  computes factorial
+}
+read x; { input an integer }
+if 0 < x then { don't compute if x <= 0 }
+  fact := 1;
+  repeat
+    fact := fact * x;
+    x := x - 1
+  until x = 0;
+  write fact  { output factorial of x }
+end";
+
+    #[test]
+    fn test_compile() -> Result<()> {
+        let compilation = compile(FACT)?;
+        assert_eq!(compilation.sym_table.st_lookup("x"), Some(0));
+        assert_eq!(compilation.sym_table.st_lookup("fact"), Some(1));
+        let tokens = compilation.emit(Emit::Tokens);
+        assert!(tokens.starts_with("5:1: reserved word: read\n5:6: ID, name= x\n5:7: ;\n"));
+        assert!(tokens.ends_with("EOF\n"));
+        assert!(compilation.emit(Emit::Ast).contains("Assign to: fact"));
+        assert!(compilation.emit(Emit::Tm).contains("HALT"));
+        assert!(compilation.warnings.is_empty());
+
+        let compilation = compile("x := 1; write y")?;
+        let codes: Vec<&str> = compilation.warnings.iter().map(|d| d.code).collect();
+        assert_eq!(codes, vec!["W0002", "W0001"]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_compile_error() {
+        assert!(compile("if 1 then write 2 end").is_err());
+        assert!(compile("x := ;").is_err());
+        let err = compile("x : 1; read 5; write (1").unwrap_err();
+        let diagnostics = err.downcast_ref::<Diagnostics>().unwrap();
+        let codes: Vec<&str> = diagnostics.0.iter().map(|d| d.code).collect();
+        assert_eq!(codes, vec!["E0002", "E0100", "E0100"]);
+
+        // lexical errors don't stop the scan
+        let err = compile("x := 1 # 2;\nwrite x $\n{ no end").unwrap_err();
+        let diagnostics = err.downcast_ref::<Diagnostics>().unwrap();
+        let codes: Vec<&str> = diagnostics.0.iter().map(|d| d.code).collect();
+        assert!(
+            codes.starts_with(&["E0001", "E0001", "E0003"]),
+            "{:?}",
+            codes
+        );
+
+        // every type error is reported, not just the first
+        let err = compile("if 1 then write 2 < 3 end;\nrepeat x := 1 until x").unwrap_err();
+        let diagnostics = err.downcast_ref::<Diagnostics>().unwrap();
+        let codes: Vec<&str> = diagnostics.0.iter().map(|d| d.code).collect();
+        assert_eq!(codes, vec!["E0201", "E0204", "E0202"]);
+
+        let err = compile("while 1 do write 1 end").unwrap_err();
+        let diagnostics = err.downcast_ref::<Diagnostics>().unwrap();
+        assert_eq!(diagnostics.0[0].code, "E0206");
+
+        let err = compile("for i := 1 to 2 < 3 step 1 = 1 do write i end").unwrap_err();
+        let diagnostics = err.downcast_ref::<Diagnostics>().unwrap();
+        let codes: Vec<&str> = diagnostics.0.iter().map(|d| d.code).collect();
+        assert_eq!(codes, vec!["E0207", "E0207"]);
+
+        let err = compile("for i := 1 to 3 step 0 do write i end").unwrap_err();
+        let diagnostics = err.downcast_ref::<Diagnostics>().unwrap();
+        assert_eq!(diagnostics.0[0].code, "E0209");
+        let err = compile("for i := 3 downto 1 step -1 do write i end").unwrap_err();
+        let diagnostics = err.downcast_ref::<Diagnostics>().unwrap();
+        assert_eq!(diagnostics.0[0].code, "E0209");
+
+        let err = compile("for b := true to 3 do write 1 end").unwrap_err();
+        let diagnostics = err.downcast_ref::<Diagnostics>().unwrap();
+        let found: Vec<(&str, &str)> = diagnostics
+            .0
+            .iter()
+            .map(|d| (d.code, d.message.as_str()))
+            .collect();
+        assert_eq!(
+            found,
+            vec![(
+                "E0207",
+                "for-loop variable `b` must be integer, found boolean"
+            )]
+        );
+    }
+
+    #[test]
+    fn test_compile_traced() -> Result<()> {
+        let trace = TraceFlags {
+            scan: true,
+            analyze: true,
+            ..TraceFlags::default()
+        };
+        let mut out = vec![];
+        compile_traced(FACT, trace, &mut out)?;
+        let listing = String::from_utf8(out)?;
+        assert!(listing.starts_with("\t5: reserved word: read\n\t5: ID, name= x\n"));
+        assert!(listing.contains("\t13: EOF\n"));
+        assert!(!listing.contains("Syntax tree:"));
+        assert!(listing.contains("Variable Name  Location   Line Numbers"));
+        assert!(listing.contains("x              0            5    6    9   10   10   11 \n"));
+        assert!(listing.ends_with("Type Checking Finished\n"));
+        Ok(())
+    }
+}
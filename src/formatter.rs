@@ -0,0 +1,455 @@
+use crate::ast::typed::{from_tree, Expr, Stmt};
+use crate::diagnostic::{Diagnostic, Diagnostics};
+use crate::parser::Parser;
+use crate::scanner::Scanner;
+use crate::token::{Span, SpannedToken, Token};
+use anyhow::Result;
+
+const INDENT: usize = 2;
+
+/// Formats `source` as canonical TINY source, keeping its comments and
+/// single blank lines. Fails with the lexical and syntax errors when
+/// `source` does not parse.
+pub fn format_source(source: &str) -> Result<String> {
+    let mut scanner = Scanner::new(source);
+    let mut tokens = vec![];
+    loop {
+        let token = scanner.get_spanned_token();
+        let done = token.token == Token::EndFile;
+        tokens.push(token);
+        if done {
+            break;
+        }
+    }
+    let mut diagnostics = scanner.diagnostics().to_vec();
+    let (tree, syntax_errors) = Parser::new(tokens.clone()).parse_recovering();
+    diagnostics.extend(syntax_errors);
+    let tree = match tree {
+        Some(tree) if !diagnostics.iter().any(Diagnostic::is_error) => tree,
+        _ => return Err(Diagnostics(diagnostics).into()),
+    };
+
+    let mut printer = Printer::new(&tokens);
+    printer.sequence(&from_tree(&tree)?);
+    Ok(printer.finish(source, scanner.comments()))
+}
+
+/// Formats a program that has no source text, so no comments either.
+pub fn format_program(stmts: &[Stmt]) -> String {
+    let mut printer = Printer::new(&[]);
+    printer.sequence(stmts);
+    printer.finish("", &[])
+}
+
+/// Formats an expression with the parentheses its precedence requires.
+pub fn format_expr(expr: &Expr) -> String {
+    match expr {
+        Expr::Const { value, .. } => value.to_string(),
+        Expr::Bool { value, .. } => value.to_string(),
+        Expr::Var { name, .. } => name.clone(),
+        Expr::Binary { op, lhs, rhs, .. } => {
+            let prec = precedence(expr);
+            // comparisons don't chain, the other operators associate left
+            let comparison = op.is_comparison();
+            let lhs = operand(lhs, |p| p < prec || (comparison && p == prec));
+            let rhs = operand(rhs, |p| p <= prec);
+            format!("{} {} {}", lhs, op.text(), rhs)
+        }
+        Expr::Unary {
+            op, operand: inner, ..
+        } => {
+            let prec = precedence(expr);
+            // `-5` would be read back as a negative literal, not as `-`
+            // applied to `5`
+            let literal = matches!(**inner, Expr::Const { value, .. } if value >= 0);
+            let inner = operand(inner, |p| p < prec || (*op == Token::Minus && literal));
+            match op {
+                Token::Not => format!("not {}", inner),
+                op => format!("{}{}", op.text(), inner),
+            }
+        }
+    }
+}
+
+// an assignment without its separator, also used for the `i := 1` of a `for`
+fn format_assign(stmt: &Stmt) -> String {
+    match stmt {
+        Stmt::Assign { name, value, .. } => format!("{} := {}", name, format_expr(value)),
+        stmt => format_program(std::slice::from_ref(stmt))
+            .trim_end()
+            .to_string(),
+    }
+}
+
+fn operand(expr: &Expr, needs_parens: impl Fn(u8) -> bool) -> String {
+    if needs_parens(precedence(expr)) {
+        format!("({})", format_expr(expr))
+    } else {
+        format_expr(expr)
+    }
+}
+
+// binding strength of the operator at the root of `expr`, following the
+// grammar levels exp, and-exp, not-exp, comparison, simple-exp, term and
+// signed-factor
+fn precedence(expr: &Expr) -> u8 {
+    match expr {
+        Expr::Binary { op: Token::Or, .. } => 1,
+        Expr::Binary { op: Token::And, .. } => 2,
+        Expr::Unary { op: Token::Not, .. } => 3,
+        Expr::Binary { op, .. } if op.is_comparison() => 4,
+        Expr::Binary {
+            op: Token::Plus | Token::Minus,
+            ..
+        } => 5,
+        Expr::Binary { .. } => 6,
+        Expr::Unary { .. } => 7,
+        Expr::Const { .. } | Expr::Bool { .. } | Expr::Var { .. } => u8::MAX,
+    }
+}
+
+// one line of output, without its comments
+struct Line {
+    indent: usize,
+    text: String,
+    // offset of the first token on the line
+    start: usize,
+}
+
+struct Printer<'a> {
+    // the scanned source, used to find keyword tokens and anchor comments
+    tokens: &'a [SpannedToken],
+    lines: Vec<Line>,
+    indent: usize,
+}
+
+impl<'a> Printer<'a> {
+    fn new(tokens: &'a [SpannedToken]) -> Self {
+        Self {
+            tokens,
+            lines: vec![],
+            indent: 0,
+        }
+    }
+
+    fn line(&mut self, text: String, start: usize) {
+        self.lines.push(Line {
+            indent: self.indent,
+            text,
+            start,
+        });
+    }
+
+    // offset of the first `token` at or after `after`
+    fn keyword(&self, token: Token, after: usize) -> usize {
+        self.tokens
+            .iter()
+            .find(|t| t.token == token && t.span.start >= after)
+            .map_or(after, |t| t.span.start)
+    }
+
+    fn sequence(&mut self, stmts: &[Stmt]) {
+        for (i, stmt) in stmts.iter().enumerate() {
+            let separator = if i + 1 < stmts.len() { ";" } else { "" };
+            self.stmt(stmt, separator);
+        }
+    }
+
+    fn block(&mut self, stmts: &[Stmt]) {
+        self.indent += INDENT;
+        self.sequence(stmts);
+        self.indent -= INDENT;
+    }
+
+    fn stmt(&mut self, stmt: &Stmt, separator: &str) {
+        let start = stmt.span().start;
+        match stmt {
+            Stmt::If {
+                cond,
+                then_branch,
+                else_branch,
+                ..
+            } => {
+                self.line(format!("if {} then", format_expr(cond)), start);
+                self.block(then_branch);
+                let mut end = sequence_end(then_branch, cond.span().end);
+                if !else_branch.is_empty() {
+                    let at = self.keyword(Token::Else, end);
+                    self.line("else".into(), at);
+                    self.block(else_branch);
+                    end = sequence_end(else_branch, at);
+                }
+                let at = self.keyword(Token::End, end);
+                self.line(format!("end{}", separator), at);
+            }
+            Stmt::Repeat { body, cond, .. } => {
+                self.line("repeat".into(), start);
+                self.block(body);
+                let at = self.keyword(Token::Until, sequence_end(body, start));
+                self.line(format!("until {}{}", format_expr(cond), separator), at);
+            }
+            Stmt::While { cond, body, .. } => {
+                self.line(format!("while {} do", format_expr(cond)), start);
+                self.block(body);
+                let at = self.keyword(Token::End, sequence_end(body, cond.span().end));
+                self.line(format!("end{}", separator), at);
+            }
+            Stmt::For {
+                init,
+                downto,
+                bound,
+                step,
+                body,
+                ..
+            } => {
+                let mut header = format!(
+                    "for {} {} {}",
+                    format_assign(init),
+                    if *downto { "downto" } else { "to" },
+                    format_expr(bound)
+                );
+                if let Some(step) = step {
+                    header.push_str(&format!(" step {}", format_expr(step)));
+                }
+                self.line(format!("{} do", header), start);
+                self.block(body);
+                let header_end = step.as_ref().unwrap_or(bound).span().end;
+                let at = self.keyword(Token::End, sequence_end(body, header_end));
+                self.line(format!("end{}", separator), at);
+            }
+            Stmt::Assign { .. } => {
+                self.line(format!("{}{}", format_assign(stmt), separator), start)
+            }
+            Stmt::Read { name, .. } => self.line(format!("read {}{}", name, separator), start),
+            Stmt::Write { value, .. } => {
+                self.line(format!("write {}{}", format_expr(value), separator), start);
+            }
+        }
+    }
+
+    // index of the line holding the token at `offset`
+    fn line_of(&self, offset: usize) -> usize {
+        self.lines
+            .iter()
+            .rposition(|line| line.start <= offset)
+            .unwrap_or(0)
+    }
+
+    // A comment after code on the same source line stays at the end of the
+    // output line holding that code; any other comment goes on its own line
+    // before the line holding the next token.
+    fn finish(self, source: &str, comments: &[Span]) -> String {
+        let code: Vec<Span> = self
+            .tokens
+            .iter()
+            .filter(|t| t.token != Token::EndFile)
+            .map(|t| t.span)
+            .collect();
+        let mut leading = vec![vec![]; self.lines.len()];
+        let mut trailing = vec![vec![]; self.lines.len()];
+        let mut rest = vec![];
+        for &comment in comments {
+            let prev = code.iter().rev().find(|t| t.end <= comment.start);
+            let next = code.iter().find(|t| t.start >= comment.end);
+            match (prev, next) {
+                _ if self.lines.is_empty() => rest.push(comment),
+                (Some(prev), _) if prev.line == comment.line => {
+                    trailing[self.line_of(prev.start)].push(comment)
+                }
+                (_, Some(next)) => leading[self.line_of(next.start)].push(comment),
+                _ => rest.push(comment),
+            }
+        }
+
+        let mut out = Output::new(source);
+        for (i, line) in self.lines.iter().enumerate() {
+            for comment in leading[i].iter() {
+                out.push(line.indent, text_of(source, *comment), *comment);
+            }
+            let next_start = self.lines.get(i + 1).map_or(usize::MAX, |l| l.start);
+            let end = code
+                .iter()
+                .filter(|t| t.start >= line.start && t.start < next_start)
+                .map(|t| t.end)
+                .max()
+                .unwrap_or(line.start);
+            let mut text = line.text.clone();
+            let mut span = Span {
+                start: line.start,
+                end,
+                ..Span::default()
+            };
+            for comment in trailing[i].iter() {
+                text.push(' ');
+                text.push_str(text_of(source, *comment));
+                span.end = comment.end;
+            }
+            out.push(line.indent, &text, span);
+        }
+        for comment in rest {
+            out.push(0, text_of(source, comment), comment);
+        }
+        out.text
+    }
+}
+
+fn sequence_end(stmts: &[Stmt], default: usize) -> usize {
+    stmts.last().map_or(default, |stmt| stmt.span().end)
+}
+
+fn text_of(source: &str, span: Span) -> &str {
+    source.get(span.start..span.end).unwrap_or("")
+}
+
+// formatted text, with a blank line wherever the source had one between
+// two consecutive pieces of output
+struct Output<'a> {
+    source: &'a str,
+    text: String,
+    prev_end: Option<usize>,
+}
+
+impl<'a> Output<'a> {
+    fn new(source: &'a str) -> Self {
+        Self {
+            source,
+            text: String::new(),
+            prev_end: None,
+        }
+    }
+
+    fn push(&mut self, indent: usize, text: &str, span: Span) {
+        if let Some(prev_end) = self.prev_end {
+            let gap = self.source.get(prev_end..span.start).unwrap_or("");
+            let mut lines = gap.split('\n').skip(1).collect::<Vec<_>>();
+            lines.pop();
+            if lines.iter().any(|l| l.trim().is_empty()) {
+                self.text.push('\n');
+            }
+        }
+        self.text.push_str(&" ".repeat(indent));
+        self.text.push_str(text);
+        self.text.push('\n');
+        self.prev_end = Some(span.end);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ast::typed::from_tree;
+    use crate::driver::scan;
+    use crate::formatter::{format_program, format_source};
+    use crate::parser::Parser;
+    use anyhow::Result;
+
+    #[test]
+    fn test_format_source() -> Result<()> {
+        let source = "{ Sample program
+  computes factorial
+}
+
+read x;  { input an integer }
+if 0<x then { don't compute if x <= 0 }
+     fact:=1;
+  repeat fact := fact*x;
+
+  { count down }
+  x:=x-1 until x=0;
+  write fact { output factorial of x }
+end";
+        let formatted = format_source(source)?;
+        assert_eq!(
+            formatted,
+            "{ Sample program
+  computes factorial
+}
+
+read x; { input an integer }
+if 0 < x then { don't compute if x <= 0 }
+  fact := 1;
+  repeat
+    fact := fact * x;
+
+    { count down }
+    x := x - 1
+  until x = 0;
+  write fact { output factorial of x }
+end
+"
+        );
+        assert_eq!(format_source(&formatted)?, formatted);
+        Ok(())
+    }
+
+    #[test]
+    fn test_format_parentheses() -> Result<()> {
+        let source = "write ((a)) + (b * c) - (d - e);
+write (a - (b - c)) * ((d + e) / f);
+write (1 < 2) = ((3 + 4) * 5 < 6)";
+        let tree = Parser::new(scan(source)).parse()?;
+        assert_eq!(
+            format_program(&from_tree(&tree)?),
+            "write a + b * c - (d - e);
+write (a - (b - c)) * ((d + e) / f);
+write (1 < 2) = ((3 + 4) * 5 < 6)
+"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_format_logical() -> Result<()> {
+        let source = "write (not (a < b)) or ((c <= d) and (e <> f));
+write not (a and b) and (c or d);
+write (not a) = b;
+write - (a+b) * -(-c) mod +d - -e";
+        let tree = Parser::new(scan(source)).parse()?;
+        assert_eq!(
+            format_program(&from_tree(&tree)?),
+            "write not a < b or c <= d and e <> f;
+write not (a and b) and (c or d);
+write (not a) = b;
+write -(a + b) * --c % +d - -e
+"
+        );
+
+        // formatting keeps `-` applied to a literal apart from a negative
+        // literal, so the printed syntax tree stays the same
+        let source = "y := -(5) - - 5 * -(-5)";
+        let tree = Parser::new(scan(source)).parse()?;
+        let formatted = format_program(&from_tree(&tree)?);
+        assert_eq!(formatted, "y := -(5) - -5 * --5\n");
+        let reparsed = Parser::new(scan(&formatted)).parse()?;
+        assert_eq!(reparsed.to_string(), tree.to_string());
+        Ok(())
+    }
+
+    #[test]
+    fn test_format_for() -> Result<()> {
+        let source = "for i:=n downto 1 step 2 do\nfor j:=1 to i do write j end end";
+        assert_eq!(
+            format_source(source)?,
+            "for i := n downto 1 step 2 do
+  for j := 1 to i do
+    write j
+  end
+end
+"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_format_while() -> Result<()> {
+        let source = "while 0<x do {countdown}\nwrite x;x:=x-1 end";
+        assert_eq!(
+            format_source(source)?,
+            "while 0 < x do {countdown}
+  write x;
+  x := x - 1
+end
+"
+        );
+        Ok(())
+    }
+}
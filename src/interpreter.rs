@@ -0,0 +1,377 @@
+use crate::ast::{Attr, ExpressionKind, Kind, StatementKind, TreeNode};
+use crate::tm::read_int;
+use crate::token::Token;
+use anyhow::Result;
+use std::collections::HashMap;
+use std::io::{BufRead, Write};
+
+/// Executes a syntax tree directly, with the integer semantics of the code
+/// the TM backend generates: arithmetic wraps around, comparisons yield 0 or
+/// 1, a condition holds when it is non-zero and variables start out as 0.
+#[derive(Debug, Clone, Default)]
+pub struct Interpreter {
+    vars: HashMap<String, i32>,
+}
+
+impl Interpreter {
+    pub fn new() -> Self {
+        Self {
+            vars: HashMap::new(),
+        }
+    }
+
+    pub fn variable(&self, name: &str) -> Option<i32> {
+        self.vars.get(name).copied()
+    }
+
+    /// Runs the statement sequence starting at `tree`, reading `read` values
+    /// from `input` and writing `write` values to `output`.
+    pub fn run(
+        &mut self,
+        tree: &TreeNode,
+        input: &mut dyn BufRead,
+        output: &mut dyn Write,
+    ) -> Result<()> {
+        self.vars.clear();
+        self.exec_sequence(Some(tree), input, output)
+    }
+
+    fn exec_sequence(
+        &mut self,
+        mut node: Option<&TreeNode>,
+        input: &mut dyn BufRead,
+        output: &mut dyn Write,
+    ) -> Result<()> {
+        while let Some(t) = node {
+            self.exec(t, input, output)?;
+            node = t.sibling.as_deref();
+        }
+        Ok(())
+    }
+
+    fn exec(
+        &mut self,
+        node: &TreeNode,
+        input: &mut dyn BufRead,
+        output: &mut dyn Write,
+    ) -> Result<()> {
+        let stmt = match &node.kind {
+            Kind::Statement(stmt) => stmt,
+            Kind::Expression(_) => {
+                return Err(anyhow::format_err!("expected a statement, found {}", node))
+            }
+        };
+        match stmt {
+            StatementKind::IfK => {
+                if self.eval_child(node, 0)? != 0 {
+                    self.exec_sequence(node.child[1].as_deref(), input, output)?;
+                } else {
+                    self.exec_sequence(node.child[2].as_deref(), input, output)?;
+                }
+            }
+            StatementKind::RepeatK => loop {
+                self.exec_sequence(node.child[0].as_deref(), input, output)?;
+                if self.eval_child(node, 1)? != 0 {
+                    break;
+                }
+            },
+            StatementKind::WhileK => {
+                while self.eval_child(node, 0)? != 0 {
+                    self.exec_sequence(node.child[1].as_deref(), input, output)?;
+                }
+            }
+            StatementKind::ForK => {
+                let init = node.child[0]
+                    .as_deref()
+                    .ok_or_else(|| anyhow::format_err!("missing loop variable in {}", node))?;
+                self.exec(init, input, output)?;
+                let name = name_of(init)?;
+                let bound = self.eval_child(node, 1)?;
+                let step = match &node.child[2] {
+                    Some(step) => self.eval(step)?,
+                    None => 1,
+                };
+                let downto = node.attr == Attr::Op(Token::Downto);
+                loop {
+                    let i = self.variable(name).unwrap_or(0);
+                    if (downto && i < bound) || (!downto && i > bound) {
+                        break;
+                    }
+                    self.exec_sequence(node.child[3].as_deref(), input, output)?;
+                    let i = self.variable(name).unwrap_or(0);
+                    let next = if downto {
+                        i.wrapping_sub(step)
+                    } else {
+                        i.wrapping_add(step)
+                    };
+                    self.vars.insert(name.into(), next);
+                    // a step that does not move the counter on, or wraps it
+                    // around, ends the loop as it does on the TM
+                    if (downto && next >= i) || (!downto && next <= i) {
+                        break;
+                    }
+                }
+            }
+            StatementKind::AssignK => {
+                let val = self.eval_child(node, 0)?;
+                self.vars.insert(name_of(node)?.into(), val);
+            }
+            StatementKind::ReadK => {
+                let val = read_int(input)?;
+                self.vars.insert(name_of(node)?.into(), val);
+            }
+            StatementKind::WriteK => {
+                let val = self.eval_child(node, 0)?;
+                writeln!(output, "{}", val)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn eval_child(&self, node: &TreeNode, idx: usize) -> Result<i32> {
+        match &node.child[idx] {
+            Some(child) => self.eval(child),
+            None => Err(anyhow::format_err!("missing operand in {}", node)),
+        }
+    }
+
+    fn eval(&self, node: &TreeNode) -> Result<i32> {
+        let expr = match &node.kind {
+            Kind::Expression(expr) => expr,
+            Kind::Statement(_) => {
+                return Err(anyhow::format_err!(
+                    "expected an expression, found {}",
+                    node
+                ))
+            }
+        };
+        match expr {
+            ExpressionKind::ConstK => match node.attr {
+                Attr::Val(val) => Ok(val),
+                Attr::Bool(b) => Ok(b as i32),
+                _ => Err(anyhow::format_err!("malformed constant {}", node)),
+            },
+            ExpressionKind::IdK => Ok(self.variable(name_of(node)?).unwrap_or(0)),
+            ExpressionKind::Opk => {
+                let lhs = self.eval_child(node, 0)?;
+                // `and` and `or` only evaluate their right operand when the
+                // left one does not decide the result
+                match &node.attr {
+                    Attr::Op(Token::Not) if node.is_unary() => return Ok((lhs == 0) as i32),
+                    Attr::Op(Token::Minus) if node.is_unary() => return Ok(lhs.wrapping_neg()),
+                    Attr::Op(Token::Plus) if node.is_unary() => return Ok(lhs),
+                    Attr::Op(Token::And) if lhs == 0 => return Ok(0),
+                    Attr::Op(Token::Or) if lhs != 0 => return Ok(1),
+                    _ => {}
+                }
+                let rhs = self.eval_child(node, 1)?;
+                match &node.attr {
+                    Attr::Op(Token::Plus) => Ok(lhs.wrapping_add(rhs)),
+                    Attr::Op(Token::Minus) => Ok(lhs.wrapping_sub(rhs)),
+                    Attr::Op(Token::Times) => Ok(lhs.wrapping_mul(rhs)),
+                    // division truncates toward zero, as on the TM
+                    Attr::Op(Token::Over | Token::Mod) if rhs == 0 => Err(anyhow::format_err!(
+                        "Division by 0 at line {}",
+                        node.line_number
+                    )),
+                    Attr::Op(Token::Over) => Ok(lhs.wrapping_div(rhs)),
+                    Attr::Op(Token::Mod) => Ok(lhs.wrapping_rem(rhs)),
+                    Attr::Op(Token::Lt) => Ok((lhs < rhs) as i32),
+                    Attr::Op(Token::Le) => Ok((lhs <= rhs) as i32),
+                    Attr::Op(Token::Gt) => Ok((lhs > rhs) as i32),
+                    Attr::Op(Token::Ge) => Ok((lhs >= rhs) as i32),
+                    Attr::Op(Token::Eq) => Ok((lhs == rhs) as i32),
+                    Attr::Op(Token::Ne) => Ok((lhs != rhs) as i32),
+                    Attr::Op(Token::And | Token::Or) => Ok((rhs != 0) as i32),
+                    attr => Err(anyhow::format_err!("unknown operator {}", attr)),
+                }
+            }
+        }
+    }
+}
+
+fn name_of(node: &TreeNode) -> Result<&str> {
+    match &node.attr {
+        Attr::Name(name) => Ok(name),
+        attr => Err(anyhow::format_err!(
+            "expected a variable name, found {}",
+            attr
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::driver::compile;
+    use crate::interpreter::Interpreter;
+    use crate::tm::Machine;
+    use anyhow::Result;
+
+    // runs `source` on both the interpreter and the TM backend
+    fn run_both(source: &str, input: &str) -> Result<(String, String)> {
+        let compilation = compile(source)?;
+
+        let mut interpreted = vec![];
+        Interpreter::new().run(&compilation.tree, &mut input.as_bytes(), &mut interpreted)?;
+
+        let mut compiled = vec![];
+        let mut machine = Machine::default();
+        machine.load(&compilation.code)?;
+        machine.run(&mut input.as_bytes(), &mut compiled)?;
+
+        Ok((
+            String::from_utf8(interpreted)?,
+            String::from_utf8(compiled)?,
+        ))
+    }
+
+    #[test]
+    fn test_interpret() -> Result<()> {
+        let compilation = compile(
+            "read x;
+if 0 < x then
+  fact := 1;
+  repeat
+    fact := fact * x;
+    x := x - 1
+  until x = 0;
+  write fact
+end",
+        )?;
+        let mut interpreter = Interpreter::new();
+        let mut output = vec![];
+        interpreter.run(&compilation.tree, &mut "5\n".as_bytes(), &mut output)?;
+        assert_eq!(String::from_utf8(output)?, "120\n");
+        assert_eq!(interpreter.variable("fact"), Some(120));
+        assert_eq!(interpreter.variable("x"), Some(0));
+        Ok(())
+    }
+
+    #[test]
+    fn test_interpret_matches_tm() -> Result<()> {
+        let programs = [
+            (
+                "read a; read b; write a / b; write a - b * (a / b)",
+                "-7\n2\n",
+            ),
+            ("x := 2147483647; write x + 1; write x * 2", ""),
+            ("read x; if x < 0 then write 0 - x else write x end", "-4\n"),
+            (
+                "i := 0; repeat if i = 2 then write i end; i := i + 1 until 3 < i",
+                "",
+            ),
+            ("write y", ""),
+            (
+                "read n; i := 0; while i < n do write i * i; i := i + 1 end; write i",
+                "4\n",
+            ),
+            ("read n; while n < 0 do write n end; write 1", "0\n"),
+            ("for i := 1 to 6 step 2 do write i end; write i", ""),
+            (
+                "read a; read b; if a <= b then write 1 end; if a > b then write 2 end;
+                if a >= b then write 3 end; if a <> b then write 4 end",
+                "3\n3\n",
+            ),
+            (
+                "read x; if x <> 0 and 10 / x > 2 or not (x < 0 or x >= 0) then write 1 else write 0 end",
+                "0\n",
+            ),
+            (
+                "read x; if x = 0 or 10 / x > 2 then write 1 end; if not x < 2 and x > 1 then write 2 end",
+                "4\n",
+            ),
+            (
+                "for i := 3 downto 1 do write i end; for j := 5 to 1 do write j end",
+                "",
+            ),
+            (
+                "read n; for i := 1 to n do n := n - 1; write i * 100 + n end",
+                "4\n",
+            ),
+            (
+                "for i := 1 to 2 do for j := i * 2 downto 1 step i do write i * 10 + j end end",
+                "",
+            ),
+            (
+                "read x; pos := x > 0; big := false; if pos and x > 9 then big := true end;
+                if big = pos then write 1 end; if not big <> false then write 2 end",
+                "12\n",
+            ),
+        ];
+        for (source, input) in programs {
+            let (interpreted, compiled) = run_both(source, input)?;
+            assert_eq!(interpreted, compiled, "{}", source);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_interpret_extremes() -> Result<()> {
+        // operands and loop bounds whose difference does not fit in 32 bits
+        let programs = [
+            (
+                "read x; if x < 1 then write 1 else write 0 end; if 1 < x then write 2 end",
+                "-2147483648\n",
+                "1\n",
+            ),
+            (
+                "read lo; for i := lo + 2 downto lo step 2 do write i end; write i",
+                "-2147483648\n",
+                "-2147483646\n-2147483648\n2147483646\n",
+            ),
+            (
+                "read lo; for i := lo to 2147483647 do write i; if i = lo + 1 then i := 2147483645 end end",
+                "-10\n",
+                "-10\n-9\n2147483646\n2147483647\n",
+            ),
+            (
+                "read s; for i := 1 to 3 step s do write i end; write i",
+                "0\n",
+                "1\n1\n",
+            ),
+            (
+                "read a; read b; if a > b then write 1 end; if a <= b then write 2 end;
+                if b >= a then write 3 end; if a <> b then write 4 end",
+                "2000000000\n-2000000000\n",
+                "1\n4\n",
+            ),
+        ];
+        for (source, input, expected) in programs {
+            let (interpreted, compiled) = run_both(source, input)?;
+            assert_eq!(interpreted, expected, "{}", source);
+            assert_eq!(compiled, expected, "{}", source);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_interpret_signed_division() -> Result<()> {
+        let source = "read a; read b; write a / b; write a % b; write a mod -b; write -a; write +b";
+        for (input, expected) in [
+            ("-7\n2\n", "-3\n-1\n-1\n7\n2\n"),
+            ("7\n-2\n", "-3\n1\n1\n-7\n-2\n"),
+            ("-2147483648\n-1\n", "-2147483648\n0\n0\n-2147483648\n-1\n"),
+        ] {
+            let (interpreted, compiled) = run_both(source, input)?;
+            assert_eq!(interpreted, expected, "{}", input);
+            assert_eq!(compiled, expected, "{}", input);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_interpret_division_by_zero() -> Result<()> {
+        let compilation = compile("read x; write 1 / x")?;
+        let err = Interpreter::new()
+            .run(&compilation.tree, &mut "0\n".as_bytes(), &mut vec![])
+            .unwrap_err();
+        assert!(err.to_string().starts_with("Division by 0"));
+
+        let compilation = compile("read x; write 1 % x")?;
+        let err = Interpreter::new()
+            .run(&compilation.tree, &mut "0\n".as_bytes(), &mut vec![])
+            .unwrap_err();
+        assert!(err.to_string().starts_with("Division by 0"));
+        Ok(())
+    }
+}
@@ -0,0 +1,292 @@
+use anyhow::Result;
+use std::fmt::{Display, Formatter, Write};
+use std::iter::Peekable;
+use std::str::Chars;
+
+/// A JSON value, just enough of it for exporting syntax trees. Numbers are
+/// integers only and objects keep their keys in insertion order.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    Number(i64),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    pub fn get(&self, key: &str) -> Option<&Json> {
+        match self {
+            Json::Object(members) => members.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    pub fn as_i64(&self) -> Option<i64> {
+        match self {
+            Json::Number(n) => Some(*n),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Json::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[Json]> {
+        match self {
+            Json::Array(items) => Some(items),
+            _ => None,
+        }
+    }
+
+    /// The value as indented text, one array element or object member per line.
+    pub fn pretty(&self) -> String {
+        let mut out = String::new();
+        self.write_pretty(&mut out, 0);
+        out
+    }
+
+    fn write_pretty(&self, out: &mut String, indent: usize) {
+        let (open, close, len) = match self {
+            Json::Array(items) => ('[', ']', items.len()),
+            Json::Object(members) => ('{', '}', members.len()),
+            _ => {
+                let _ = write!(out, "{}", self);
+                return;
+            }
+        };
+        if len == 0 {
+            out.push(open);
+            out.push(close);
+            return;
+        }
+        out.push(open);
+        out.push('\n');
+        let pad = " ".repeat(indent + 2);
+        for i in 0..len {
+            out.push_str(&pad);
+            match self {
+                Json::Array(items) => items[i].write_pretty(out, indent + 2),
+                Json::Object(members) => {
+                    let _ = write!(out, "{}: ", Json::String(members[i].0.clone()));
+                    members[i].1.write_pretty(out, indent + 2);
+                }
+                _ => unreachable!(),
+            }
+            if i + 1 < len {
+                out.push(',');
+            }
+            out.push('\n');
+        }
+        out.push_str(&" ".repeat(indent));
+        out.push(close);
+    }
+
+    pub fn parse(text: &str) -> Result<Json> {
+        let mut chars = text.chars().peekable();
+        let value = parse_value(&mut chars)?;
+        skip_whitespace(&mut chars);
+        match chars.next() {
+            None => Ok(value),
+            Some(c) => Err(anyhow::format_err!("unexpected `{}` after JSON value", c)),
+        }
+    }
+}
+
+impl Display for Json {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Json::Null => write!(f, "null"),
+            Json::Bool(b) => write!(f, "{}", b),
+            Json::Number(n) => write!(f, "{}", n),
+            Json::String(s) => {
+                f.write_char('"')?;
+                for c in s.chars() {
+                    match c {
+                        '"' => f.write_str("\\\"")?,
+                        '\\' => f.write_str("\\\\")?,
+                        '\n' => f.write_str("\\n")?,
+                        '\r' => f.write_str("\\r")?,
+                        '\t' => f.write_str("\\t")?,
+                        c if (c as u32) < 0x20 => write!(f, "\\u{:04x}", c as u32)?,
+                        c => f.write_char(c)?,
+                    }
+                }
+                f.write_char('"')
+            }
+            Json::Array(items) => {
+                f.write_char('[')?;
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        f.write_char(',')?;
+                    }
+                    write!(f, "{}", item)?;
+                }
+                f.write_char(']')
+            }
+            Json::Object(members) => {
+                f.write_char('{')?;
+                for (i, (key, value)) in members.iter().enumerate() {
+                    if i > 0 {
+                        f.write_char(',')?;
+                    }
+                    write!(f, "{}:{}", Json::String(key.clone()), value)?;
+                }
+                f.write_char('}')
+            }
+        }
+    }
+}
+
+fn skip_whitespace(chars: &mut Peekable<Chars>) {
+    while chars.next_if(|c| c.is_ascii_whitespace()).is_some() {}
+}
+
+fn expect(chars: &mut Peekable<Chars>, expected: char) -> Result<()> {
+    skip_whitespace(chars);
+    match chars.next() {
+        Some(c) if c == expected => Ok(()),
+        Some(c) => Err(anyhow::format_err!(
+            "expected `{}`, found `{}`",
+            expected,
+            c
+        )),
+        None => Err(anyhow::format_err!(
+            "expected `{}`, found end of input",
+            expected
+        )),
+    }
+}
+
+fn parse_value(chars: &mut Peekable<Chars>) -> Result<Json> {
+    skip_whitespace(chars);
+    match chars.peek().copied() {
+        Some('{') => {
+            chars.next();
+            let mut members = vec![];
+            skip_whitespace(chars);
+            if chars.next_if_eq(&'}').is_some() {
+                return Ok(Json::Object(members));
+            }
+            loop {
+                skip_whitespace(chars);
+                let key = parse_string(chars)?;
+                expect(chars, ':')?;
+                members.push((key, parse_value(chars)?));
+                skip_whitespace(chars);
+                if chars.next_if_eq(&',').is_none() {
+                    break;
+                }
+            }
+            expect(chars, '}')?;
+            Ok(Json::Object(members))
+        }
+        Some('[') => {
+            chars.next();
+            let mut items = vec![];
+            skip_whitespace(chars);
+            if chars.next_if_eq(&']').is_some() {
+                return Ok(Json::Array(items));
+            }
+            loop {
+                items.push(parse_value(chars)?);
+                skip_whitespace(chars);
+                if chars.next_if_eq(&',').is_none() {
+                    break;
+                }
+            }
+            expect(chars, ']')?;
+            Ok(Json::Array(items))
+        }
+        Some('"') => Ok(Json::String(parse_string(chars)?)),
+        Some(c) if c == '-' || c.is_ascii_digit() => {
+            let mut text = String::new();
+            while let Some(c) = chars.next_if(|c| *c == '-' || c.is_ascii_digit()) {
+                text.push(c);
+            }
+            text.parse()
+                .map(Json::Number)
+                .map_err(|_| anyhow::format_err!("invalid number `{}`", text))
+        }
+        Some(c) if c.is_ascii_alphabetic() => {
+            let mut word = String::new();
+            while let Some(c) = chars.next_if(|c| c.is_ascii_alphabetic()) {
+                word.push(c);
+            }
+            match word.as_str() {
+                "null" => Ok(Json::Null),
+                "true" => Ok(Json::Bool(true)),
+                "false" => Ok(Json::Bool(false)),
+                _ => Err(anyhow::format_err!("unexpected `{}`", word)),
+            }
+        }
+        Some(c) => Err(anyhow::format_err!("unexpected `{}`", c)),
+        None => Err(anyhow::format_err!("unexpected end of input")),
+    }
+}
+
+fn parse_string(chars: &mut Peekable<Chars>) -> Result<String> {
+    expect(chars, '"')?;
+    let mut text = String::new();
+    loop {
+        match chars.next() {
+            Some('"') => return Ok(text),
+            Some('\\') => match chars.next() {
+                Some('n') => text.push('\n'),
+                Some('r') => text.push('\r'),
+                Some('t') => text.push('\t'),
+                Some('b') => text.push('\u{8}'),
+                Some('f') => text.push('\u{c}'),
+                Some('u') => {
+                    let hex: String = chars.by_ref().take(4).collect();
+                    let c = u32::from_str_radix(&hex, 16)
+                        .ok()
+                        .and_then(char::from_u32)
+                        .ok_or_else(|| anyhow::format_err!("invalid escape `\\u{}`", hex))?;
+                    text.push(c);
+                }
+                Some(c @ ('"' | '\\' | '/')) => text.push(c),
+                Some(c) => return Err(anyhow::format_err!("invalid escape `\\{}`", c)),
+                None => break,
+            },
+            Some(c) => text.push(c),
+            None => break,
+        }
+    }
+    Err(anyhow::format_err!("unterminated string"))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::json::Json;
+    use anyhow::Result;
+
+    #[test]
+    fn test_json() -> Result<()> {
+        let text = r#" {"a": [1, -2, null, true], "b\n": {"c": "x\"yA"}, "d": []} "#;
+        let json = Json::parse(text)?;
+        assert_eq!(
+            json.get("a").and_then(|a| a.as_array()).map(|a| a.len()),
+            Some(4)
+        );
+        assert_eq!(
+            json.get("b\n")
+                .and_then(|b| b.get("c"))
+                .and_then(Json::as_str),
+            Some("x\"yA")
+        );
+        assert_eq!(
+            json.to_string(),
+            r#"{"a":[1,-2,null,true],"b\n":{"c":"x\"yA"},"d":[]}"#
+        );
+        assert_eq!(Json::parse(&json.pretty())?, json);
+        assert!(Json::parse("[1, 2").is_err());
+        assert!(Json::parse("{} x").is_err());
+        Ok(())
+    }
+}
@@ -1,6 +1,16 @@
 pub mod analyzer;
 pub mod ast;
+pub mod codegen;
+pub mod cst;
+pub mod diagnostic;
+pub mod dot;
+pub mod driver;
+pub mod formatter;
+pub mod interpreter;
+pub mod json;
+pub mod liveness;
 pub mod parser;
 pub mod scanner;
 pub mod symtable;
+pub mod tm;
 pub mod token;
@@ -0,0 +1,241 @@
+use crate::ast::{Attr, ExpressionKind, Kind, StatementKind, TreeNode};
+use crate::diagnostic::Diagnostic;
+use crate::symtable::SymTable;
+use crate::token::Span;
+use std::collections::{BTreeMap, BTreeSet};
+
+type Live = BTreeSet<String>;
+
+/// Backward liveness analysis over the syntax tree. A variable is live at a
+/// point when its current value may still be used; storing to a variable
+/// that is dead right after the store is wasted work.
+///
+/// Warns about variables that are given values but never used at all, once
+/// at their first definition, and about each store whose value is always
+/// overwritten before being read. The warnings come in no particular order.
+pub fn check_dead_stores(tree: Option<&TreeNode>, sym_table: &SymTable) -> Vec<Diagnostic> {
+    let mut liveness = Liveness {
+        sym_table,
+        report: true,
+        unused: BTreeMap::new(),
+        warnings: vec![],
+    };
+    liveness.sequence(tree, Live::new());
+    for (name, span) in std::mem::take(&mut liveness.unused) {
+        let message = format!("variable `{}` is assigned but never used", name);
+        liveness
+            .warnings
+            .push(Diagnostic::warning("W0002", message, span).with_label("assigned here"));
+    }
+    liveness.warnings
+}
+
+struct Liveness<'a> {
+    sym_table: &'a SymTable,
+    // off while a loop is being iterated to its fixed point
+    report: bool,
+    // the first definition of each never used variable
+    unused: BTreeMap<String, Span>,
+    warnings: Vec<Diagnostic>,
+}
+
+impl Liveness<'_> {
+    // the variables live before `node` and its siblings, given those live
+    // after the last of them
+    fn sequence(&mut self, node: Option<&TreeNode>, mut live: Live) -> Live {
+        let mut stmts = vec![];
+        let mut next = node;
+        while let Some(t) = next {
+            stmts.push(t);
+            next = t.sibling.as_deref();
+        }
+        for stmt in stmts.into_iter().rev() {
+            live = self.stmt(stmt, live);
+        }
+        live
+    }
+
+    fn stmt(&mut self, node: &TreeNode, mut live: Live) -> Live {
+        let child = |i: usize| node.child[i].as_deref();
+        match node.kind {
+            Kind::Statement(StatementKind::IfK) => {
+                let mut before = self.sequence(child(1), live.clone());
+                before.extend(self.sequence(child(2), live));
+                uses(child(0), &mut before);
+                before
+            }
+            Kind::Statement(StatementKind::RepeatK) => {
+                // the condition runs after the body, and when it fails the
+                // body runs again, so whatever is live at the start of the
+                // body is live after the condition too
+                let report = std::mem::replace(&mut self.report, false);
+                let mut head = Live::new();
+                loop {
+                    let mut after_body = live.clone();
+                    after_body.extend(head.iter().cloned());
+                    uses(child(1), &mut after_body);
+                    let next = self.sequence(child(0), after_body);
+                    if next == head {
+                        break;
+                    }
+                    head = next;
+                }
+                self.report = report;
+                live.extend(head);
+                uses(child(1), &mut live);
+                self.sequence(child(0), live)
+            }
+            Kind::Statement(StatementKind::WhileK) => {
+                // the condition is tested before every run of the body and
+                // once more on the way out
+                let report = std::mem::replace(&mut self.report, false);
+                let mut head = Live::new();
+                loop {
+                    let mut after_body = live.clone();
+                    after_body.extend(head.iter().cloned());
+                    let mut next = self.sequence(child(1), after_body);
+                    next.extend(live.iter().cloned());
+                    uses(child(0), &mut next);
+                    if next == head {
+                        break;
+                    }
+                    head = next;
+                }
+                self.report = report;
+                self.sequence(child(1), head.clone());
+                head
+            }
+            Kind::Statement(StatementKind::ForK) => {
+                // the counter is read by the test before each run of the
+                // body and read and stored again after it; the bound and
+                // step are read once, before the first test
+                let counter = match child(0).map(|init| &init.attr) {
+                    Some(Attr::Name(name)) => Some(name.clone()),
+                    _ => None,
+                };
+                live.extend(counter.clone());
+                let report = std::mem::replace(&mut self.report, false);
+                let mut head = Live::new();
+                loop {
+                    let mut after_body = live.clone();
+                    after_body.extend(head.iter().cloned());
+                    let mut next = self.sequence(child(3), after_body);
+                    next.extend(live.iter().cloned());
+                    if next == head {
+                        break;
+                    }
+                    head = next;
+                }
+                self.report = report;
+                self.sequence(child(3), head.clone());
+                uses(child(1), &mut head);
+                uses(child(2), &mut head);
+                self.sequence(child(0), head)
+            }
+            Kind::Statement(StatementKind::AssignK | StatementKind::ReadK) => {
+                if let Attr::Name(name) = &node.attr {
+                    if !live.remove(name) {
+                        self.dead_store(node, name);
+                    }
+                }
+                uses(child(0), &mut live);
+                live
+            }
+            _ => {
+                uses(child(0), &mut live);
+                live
+            }
+        }
+    }
+
+    fn dead_store(&mut self, node: &TreeNode, name: &str) {
+        if !self.report {
+            return;
+        }
+        let used = self.sym_table.get(name).is_some_and(|info| info.is_used());
+        if used {
+            let message = format!("value assigned to `{}` is never read", name);
+            self.warnings.push(
+                Diagnostic::warning("W0003", message, node.span)
+                    .with_label("overwritten before it is read"),
+            );
+        } else {
+            // the pass runs backward, but visits the branches of an `if`
+            // in order, so keep whichever definition comes first
+            let first = self.unused.entry(name.to_string()).or_insert(node.span);
+            if node.span.start < first.start {
+                *first = node.span;
+            }
+        }
+    }
+}
+
+// adds the variables read by the expression `node`
+fn uses(node: Option<&TreeNode>, live: &mut Live) {
+    let Some(node) = node else {
+        return;
+    };
+    if let (Kind::Expression(ExpressionKind::IdK), Attr::Name(name)) = (&node.kind, &node.attr) {
+        live.insert(name.clone());
+    }
+    for child in node.child.iter() {
+        uses(child.as_deref(), live);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::analyzer::Analyzer;
+    use crate::driver::scan;
+    use crate::liveness::check_dead_stores;
+    use crate::parser::Parser;
+    use anyhow::Result;
+
+    #[test]
+    fn test_check_dead_stores() -> Result<()> {
+        let source = "read x;
+y := 1;
+y := x;
+read t; t := 2;
+u := 1;
+u := 2;
+if x < 1 then
+  w := 1
+else
+  w := 2
+end;
+if x < 1 then z := 1 else z := 2 end;
+repeat
+  n := x;
+  x := x - 1
+until x = 0;
+write y + z";
+        let node = Parser::new(scan(source)).parse()?;
+        let root = Some(Box::new(node));
+        let sym_table = Analyzer::new().build_symbol_table(&root);
+        let mut warnings = check_dead_stores(root.as_deref(), &sym_table);
+        warnings.sort_by_key(|warning| warning.span.start);
+        let found: Vec<(&str, &str, usize)> = warnings
+            .iter()
+            .map(|d| (d.code, d.message.as_str(), d.span.line))
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                ("W0003", "value assigned to `y` is never read", 2),
+                ("W0002", "variable `t` is assigned but never used", 4),
+                ("W0002", "variable `u` is assigned but never used", 5),
+                ("W0002", "variable `w` is assigned but never used", 8),
+                ("W0002", "variable `n` is assigned but never used", 14),
+            ]
+        );
+
+        // the value stored at the end of a loop body is read by the next
+        // iteration
+        let node = Parser::new(scan("i := 0; repeat write i; i := i + 1 until 0 < 1")).parse()?;
+        let root = Some(Box::new(node));
+        let sym_table = Analyzer::new().build_symbol_table(&root);
+        assert!(check_dead_stores(root.as_deref(), &sym_table).is_empty());
+        Ok(())
+    }
+}
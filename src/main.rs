@@ -1,3 +1,284 @@
-fn main() {
-    println!("Hello, world!");
+use anyhow::{Context, Result};
+use std::fs;
+use std::io::{self, Read, Write};
+use std::process::ExitCode;
+use tiny_compiler_rust::diagnostic::{Diagnostic, Diagnostics};
+use tiny_compiler_rust::driver::{self, Emit, TraceFlags};
+use tiny_compiler_rust::formatter;
+use tiny_compiler_rust::interpreter::Interpreter;
+use tiny_compiler_rust::tm::{self, Machine};
+
+const USAGE: &str = "usage: tiny [OPTIONS] [FILE]
+
+Compiles the TINY program in FILE (or stdin when FILE is omitted or `-`).
+Trace listings are written to stderr. A FILE ending in `.tm` is taken to be
+a TM assembly listing and is run on the simulator without compiling.
+
+options:
+  -o <PATH>        write the output artifact to PATH instead of stdout
+  --emit <KIND>    output artifact: tokens, ast, json, sexp, dot, tm
+                   (default: tm)
+  --trace-scan     list every token with its line number
+  --trace-parse    print the syntax tree
+  --trace-analyze  print the symbol table and type checking progress
+  --trace-code     print the generated code
+  --trace          enable all of the trace options above
+  --run            run the compiled program on the TM simulator; the output
+                   artifact is then only written when `-o` is given
+  --interpret      execute the syntax tree directly instead of running TM
+                   code; `-o` is handled as for `--run`, but the two
+                   cannot be combined
+  --imem-size <N>  TM instruction memory size (default: 1024)
+  --dmem-size <N>  TM data memory size (default: 1024)
+  -h, --help       print this help
+
+`tiny fmt [--check] [FILE]...` formats TINY source files in place, or stdin
+to stdout when no FILE is given. With `--check` nothing is written; the
+files that are not formatted are listed and the exit status is 1.";
+
+struct Options {
+    input: Option<String>,
+    output: Option<String>,
+    emit: Emit,
+    trace: TraceFlags,
+    run: bool,
+    interpret: bool,
+    imem_size: usize,
+    dmem_size: usize,
+}
+
+fn parse_args(args: &[String]) -> Result<Option<Options>> {
+    let mut options = Options {
+        input: None,
+        output: None,
+        emit: Emit::Tm,
+        trace: TraceFlags::default(),
+        run: false,
+        interpret: false,
+        imem_size: tm::IADDR_SIZE,
+        dmem_size: tm::DADDR_SIZE,
+    };
+    let mut it = args.iter();
+    while let Some(arg) = it.next() {
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(None),
+            "-o" => {
+                let path = it.next().context("`-o` requires a path")?;
+                options.output = Some(path.clone());
+            }
+            "--emit" => {
+                let kind = it.next().context("`--emit` requires a kind")?;
+                options.emit = kind.parse()?;
+            }
+            "--trace-scan" => options.trace.scan = true,
+            "--trace-parse" => options.trace.parse = true,
+            "--trace-analyze" => options.trace.analyze = true,
+            "--trace-code" => options.trace.code = true,
+            "--trace" => options.trace = TraceFlags::all(),
+            "--run" => options.run = true,
+            "--interpret" => options.interpret = true,
+            "--imem-size" => options.imem_size = parse_size(it.next(), arg)?,
+            "--dmem-size" => options.dmem_size = parse_size(it.next(), arg)?,
+            "-" => options.input = None,
+            _ if arg.starts_with('-') => {
+                return Err(anyhow::format_err!("unknown option `{}`", arg))
+            }
+            _ => {
+                if options.input.is_some() {
+                    return Err(anyhow::format_err!("more than one input file given"));
+                }
+                options.input = Some(arg.clone());
+            }
+        }
+    }
+    // both would execute the program, each reading from stdin
+    if options.run && options.interpret {
+        return Err(anyhow::format_err!(
+            "`--run` and `--interpret` cannot be used together"
+        ));
+    }
+    Ok(Some(options))
+}
+
+fn parse_size(value: Option<&String>, option: &str) -> Result<usize> {
+    let value = value.with_context(|| format!("`{}` requires a size", option))?;
+    match value.parse::<usize>() {
+        Ok(size) if size > 0 => Ok(size),
+        _ => Err(anyhow::format_err!(
+            "invalid size `{}` for `{}`",
+            value,
+            option
+        )),
+    }
+}
+
+fn read_source(input: &Option<String>) -> Result<String> {
+    match input {
+        Some(path) => fs::read_to_string(path).with_context(|| format!("cannot read `{}`", path)),
+        None => {
+            let mut source = String::new();
+            io::stdin()
+                .read_to_string(&mut source)
+                .context("cannot read stdin")?;
+            Ok(source)
+        }
+    }
+}
+
+fn write_output(output: &Option<String>, text: &str) -> Result<()> {
+    match output {
+        Some(path) => fs::write(path, text).with_context(|| format!("cannot write `{}`", path)),
+        None => {
+            io::stdout().write_all(text.as_bytes())?;
+            Ok(())
+        }
+    }
+}
+
+// renders a compile error against the source, leaving a short summary to return
+fn report(err: anyhow::Error, source: &str, file_name: &str) -> anyhow::Error {
+    let diagnostics = match (
+        err.downcast_ref::<Diagnostic>(),
+        err.downcast_ref::<Diagnostics>(),
+    ) {
+        (Some(diagnostic), _) => vec![diagnostic.clone()],
+        (_, Some(diagnostics)) => diagnostics.0.clone(),
+        _ => return err,
+    };
+    for diagnostic in diagnostics.iter() {
+        eprintln!("{}", diagnostic.render(source, file_name));
+    }
+    match Diagnostics(diagnostics).error_count() {
+        1 => anyhow::format_err!("could not compile `{}` due to previous error", file_name),
+        n => anyhow::format_err!(
+            "could not compile `{}` due to {} previous errors",
+            file_name,
+            n
+        ),
+    }
+}
+
+fn run(options: &Options) -> Result<()> {
+    let source = read_source(&options.input)?;
+    let is_listing = matches!(&options.input, Some(path) if path.ends_with(".tm"));
+    let code = if is_listing {
+        source
+    } else {
+        let compilation = driver::compile_traced(&source, options.trace, &mut io::stderr())
+            .map_err(|err| report(err, &source, file_name(&options.input)))?;
+        for warning in compilation.warnings.iter() {
+            eprintln!("{}", warning.render(&source, file_name(&options.input)));
+        }
+        let executes = options.run || options.interpret;
+        if !executes || options.output.is_some() {
+            write_output(&options.output, &compilation.emit(options.emit))?;
+        }
+        if options.interpret {
+            let mut interpreter = Interpreter::new();
+            interpreter.run(
+                &compilation.tree,
+                &mut io::stdin().lock(),
+                &mut io::stdout(),
+            )?;
+        }
+        compilation.code
+    };
+
+    if options.run || is_listing {
+        let mut machine = Machine::new(options.imem_size, options.dmem_size);
+        machine.load(&code)?;
+        machine.run(&mut io::stdin().lock(), &mut io::stdout())?;
+    }
+    Ok(())
+}
+
+fn file_name(input: &Option<String>) -> &str {
+    input.as_deref().unwrap_or("<stdin>")
+}
+
+struct FmtOptions {
+    inputs: Vec<Option<String>>,
+    check: bool,
+}
+
+fn parse_fmt_args(args: &[String]) -> Result<Option<FmtOptions>> {
+    let mut options = FmtOptions {
+        inputs: vec![],
+        check: false,
+    };
+    for arg in args {
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(None),
+            "--check" => options.check = true,
+            _ if arg.starts_with('-') => {
+                return Err(anyhow::format_err!("unknown option `{}`", arg))
+            }
+            _ => options.inputs.push(Some(arg.clone())),
+        }
+    }
+    if options.inputs.is_empty() {
+        options.inputs.push(None);
+    }
+    Ok(Some(options))
+}
+
+// returns whether every input was formatted already
+fn fmt(options: &FmtOptions) -> Result<bool> {
+    let mut formatted_all = true;
+    for input in options.inputs.iter() {
+        let source = read_source(input)?;
+        let formatted = formatter::format_source(&source)
+            .map_err(|err| report(err, &source, file_name(input)))?;
+        formatted_all &= formatted == source;
+        if options.check {
+            if formatted != source {
+                println!("{} is not formatted", file_name(input));
+            }
+        } else if formatted != source || input.is_none() {
+            // files are rewritten in place, stdin goes to stdout
+            write_output(input, &formatted)?;
+        }
+    }
+    Ok(formatted_all)
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.first().map(String::as_str) == Some("fmt") {
+        let options = match parse_fmt_args(&args[1..]) {
+            Ok(Some(options)) => options,
+            Ok(None) => {
+                println!("{}", USAGE);
+                return ExitCode::SUCCESS;
+            }
+            Err(err) => {
+                eprintln!("tiny: {}\n\n{}", err, USAGE);
+                return ExitCode::from(2);
+            }
+        };
+        return match fmt(&options) {
+            Ok(formatted) if formatted || !options.check => ExitCode::SUCCESS,
+            Ok(_) => ExitCode::FAILURE,
+            Err(err) => {
+                eprintln!("tiny: error: {:#}", err);
+                ExitCode::FAILURE
+            }
+        };
+    }
+    let options = match parse_args(&args) {
+        Ok(Some(options)) => options,
+        Ok(None) => {
+            println!("{}", USAGE);
+            return ExitCode::SUCCESS;
+        }
+        Err(err) => {
+            eprintln!("tiny: {}\n\n{}", err, USAGE);
+            return ExitCode::from(2);
+        }
+    };
+    if let Err(err) = run(&options) {
+        eprintln!("tiny: error: {:#}", err);
+        return ExitCode::FAILURE;
+    }
+    ExitCode::SUCCESS
 }
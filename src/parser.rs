@@ -1,209 +1,582 @@
 use crate::ast::ExpressionKind::Opk;
 use crate::ast::StatementKind::{AssignK, ReadK, WriteK};
 use crate::ast::{Attr, ExpressionKind, StatementKind, TreeNode};
-use crate::token::Token;
+use crate::cst::SyntaxKind;
+use crate::diagnostic::Diagnostic;
+use crate::token::{Span, SpannedToken, Token};
 use anyhow::Result;
 
+/// What the parser did, in source order: nodes of the concrete syntax tree
+/// open and close around the tokens they consumed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Event {
+    Start(SyntaxKind),
+    // index of the consumed token in the parser's input
+    Token(usize),
+    Finish,
+}
+
 pub struct Parser {
-    tokens: Vec<Token>,
+    tokens: Vec<SpannedToken>,
     cur_idx: usize,
+    events: Vec<Event>,
+    diagnostics: Vec<Diagnostic>,
+    // set after a syntax error, cleared once the parser consumes a token it
+    // expected; further errors are not reported meanwhile
+    panic_mode: bool,
 }
 
 impl Parser {
-    pub fn new(tokens: Vec<Token>) -> Self {
-        Self { tokens, cur_idx: 0 }
+    /// Accepts plain `Token`s as well as `SpannedToken`s; nodes built from
+    /// plain tokens have empty spans and line number 0.
+    pub fn new<T: Into<SpannedToken>>(tokens: Vec<T>) -> Self {
+        let mut tokens: Vec<SpannedToken> = tokens.into_iter().map(Into::into).collect();
+        if tokens.last().map(|t| &t.token) != Some(&Token::EndFile) {
+            let end = tokens.last().map_or(0, |t| t.span.end);
+            let span = Span {
+                start: end,
+                end,
+                ..tokens.last().map_or(Span::default(), |t| t.span)
+            };
+            tokens.push(SpannedToken::new(Token::EndFile, span));
+        }
+        Self {
+            tokens,
+            cur_idx: 0,
+            events: vec![],
+            diagnostics: vec![],
+            panic_mode: false,
+        }
     }
 
+    /// Parses the whole program, failing with the first syntax error.
     pub fn parse(&mut self) -> Result<TreeNode> {
-        self.stmt_sequence()
+        let (tree, mut diagnostics) = self.parse_recovering();
+        if !diagnostics.is_empty() {
+            return Err(diagnostics.remove(0).into());
+        }
+        tree.ok_or_else(|| anyhow::format_err!("empty program"))
+    }
+
+    /// Parses the whole program, recovering from syntax errors by skipping
+    /// to the next `;`, `end`, `else` or `until`. Returns every syntax error
+    /// found together with whatever part of the tree could be built; the
+    /// children of nodes that failed to parse are left as `None`.
+    pub fn parse_recovering(&mut self) -> (Option<TreeNode>, Vec<Diagnostic>) {
+        self.cur_idx = 0;
+        self.panic_mode = false;
+        self.events.clear();
+        self.start_node(SyntaxKind::Program);
+        let mut tree = self.stmt_sequence();
+        while self.token_ref() != &Token::EndFile {
+            // a stray `end`, `else` or `until` ended the sequence early; when
+            // the parser is still recovering from an error at this token, it
+            // has already been reported
+            self.error_expected("end of file");
+            self.start_node(SyntaxKind::Error);
+            self.bump();
+            self.finish_node();
+            let rest = self.stmt_sequence();
+            append_sibling(&mut tree, rest);
+        }
+        self.finish_node();
+        (tree, std::mem::take(&mut self.diagnostics))
+    }
+
+    /// The events of the last parse; the end of file token is never consumed.
+    pub fn events(&self) -> &[Event] {
+        &self.events
+    }
+
+    fn start_node(&mut self, kind: SyntaxKind) {
+        self.events.push(Event::Start(kind));
+    }
+
+    fn finish_node(&mut self) {
+        self.events.push(Event::Finish);
+    }
+
+    // position to open a node at later, once it is known that the events
+    // recorded since then belong to it
+    fn checkpoint(&self) -> usize {
+        self.events.len()
     }
 
-    fn get_token(&mut self) -> Option<&Token> {
-        self.cur_idx += 1;
-        self.tokens.get(self.cur_idx - 1)
+    fn start_node_at(&mut self, checkpoint: usize, kind: SyntaxKind) {
+        self.events.insert(checkpoint, Event::Start(kind));
     }
 
     fn token_ref(&self) -> &Token {
-        &self.tokens[self.cur_idx]
+        &self.tokens[self.cur_idx].token
+    }
+
+    // span of the current token
+    fn token_span(&self) -> Span {
+        self.tokens[self.cur_idx].span
+    }
+
+    // span from `start` up to the end of the last consumed token
+    fn span_from(&self, start: Span) -> Span {
+        match self.cur_idx.checked_sub(1).and_then(|i| self.tokens.get(i)) {
+            Some(prev) => start.to(prev.span),
+            None => start,
+        }
+    }
+
+    // consumes the current token as part of the program; never moves past EOF
+    fn advance(&mut self) {
+        self.bump();
+        self.panic_mode = false;
+    }
+
+    fn bump(&mut self) {
+        if self.token_ref() != &Token::EndFile {
+            self.events.push(Event::Token(self.cur_idx));
+            self.cur_idx += 1;
+        }
+    }
+
+    fn at_sync_token(&self) -> bool {
+        matches!(
+            self.token_ref(),
+            Token::Semi | Token::End | Token::Else | Token::Until | Token::EndFile
+        )
+    }
+
+    // skips tokens until one that can follow a statement
+    fn synchronize(&mut self) {
+        if self.at_sync_token() {
+            return;
+        }
+        self.start_node(SyntaxKind::Error);
+        while !self.at_sync_token() {
+            self.bump();
+        }
+        self.finish_node();
+    }
+
+    fn error(&mut self, diagnostic: Diagnostic) {
+        // invalid tokens have already been reported by the scanner
+        let is_lexical = matches!(self.token_ref(), Token::Error(_));
+        if !self.panic_mode && !is_lexical {
+            self.diagnostics.push(diagnostic);
+        }
+        self.panic_mode = true;
+    }
+
+    fn error_expected(&mut self, expected: &str) {
+        let message = format!(
+            "expected {}, found {}",
+            expected,
+            self.token_ref().describe()
+        );
+        let diagnostic = Diagnostic::error("E0100", message, self.token_span())
+            .with_label(&format!("expected {}", expected));
+        self.error(diagnostic);
+    }
+
+    fn starts_statement(&self) -> bool {
+        matches!(
+            self.token_ref(),
+            Token::If
+                | Token::Repeat
+                | Token::While
+                | Token::For
+                | Token::Id(_)
+                | Token::Read
+                | Token::Write
+        )
     }
 
     // stmt-sequence -> statement {; statement }
-    fn stmt_sequence(&mut self) -> Result<TreeNode> {
-        let mut t: Option<Box<TreeNode>> = Some(Box::new(self.statement()?));
-        let mut p = t.as_mut().unwrap();
+    fn stmt_sequence(&mut self) -> Option<TreeNode> {
+        let mut t = self.statement();
         while self.token_ref() != &Token::EndFile
             && self.token_ref() != &Token::End
             && self.token_ref() != &Token::Else
             && self.token_ref() != &Token::Until
         {
-            self.match_token(Token::Semi);
-            let q = self.statement()?;
-            p.sibling = Some(Box::new(q));
-            p = p.sibling.as_mut().unwrap();
-        }
-        Ok(*(t.unwrap()))
-    }
-
-    // statement -> if-stmt | repeat-stmt | assign-stmt | read-stmt | write-stmt
-    fn statement(&mut self) -> Result<TreeNode> {
-        let token = self.token_ref();
-        let t = match *token {
-            Token::If => self.if_stmt()?,
-            Token::Repeat => self.repeat_stmt()?,
-            Token::Id(_) => self.assign_stmt()?,
-            Token::Read => self.read_stmt()?,
-            Token::Write => self.write_stmt()?,
-            _ => return Err(anyhow::format_err!("{}", token)),
+            if !self.match_token(Token::Semi) && !self.starts_statement() {
+                self.synchronize();
+                continue;
+            }
+            let q = self.statement();
+            append_sibling(&mut t, q);
+        }
+        t
+    }
+
+    // statement -> if-stmt | repeat-stmt | while-stmt | for-stmt | assign-stmt
+    //            | read-stmt | write-stmt
+    fn statement(&mut self) -> Option<TreeNode> {
+        let t = match *self.token_ref() {
+            Token::If => self.if_stmt(),
+            Token::Repeat => self.repeat_stmt(),
+            Token::While => self.while_stmt(),
+            Token::For => self.for_stmt(),
+            Token::Id(_) => self.assign_stmt(),
+            Token::Read => self.read_stmt(),
+            Token::Write => self.write_stmt(),
+            _ => {
+                let message = format!(
+                    "expected a statement, found {}",
+                    self.token_ref().describe()
+                );
+                let diagnostic = Diagnostic::error("E0101", message, self.token_span())
+                    .with_label("expected a statement")
+                    .with_note(
+                        "statements start with `if`, `repeat`, `while`, `for`, `read`, `write` or a variable",
+                    );
+                self.error(diagnostic);
+                self.synchronize();
+                return None;
+            }
         };
-        Ok(t)
+        Some(t)
     }
 
     // if-stmt -> if exp then stmt-sequence | else stmt-sequence | end
-    fn if_stmt(&mut self) -> Result<TreeNode> {
+    fn if_stmt(&mut self) -> TreeNode {
+        self.start_node(SyntaxKind::IfStmt);
         let mut t = TreeNode::new_statement_node(StatementKind::IfK);
+        let start = self.token_span();
         self.match_token(Token::If);
-        t.child[0] = Some(Box::new(self.expr()?));
+        t.child[0] = self.expr().map(Box::new);
         self.match_token(Token::Then);
-        t.child[1] = Some(Box::new(self.stmt_sequence()?));
-        let token = self.token_ref();
-        if token == &Token::Else {
+        t.child[1] = self.stmt_sequence().map(Box::new);
+        if self.token_ref() == &Token::Else {
             self.match_token(Token::Else);
-            t.child[2] = Some(Box::new(self.stmt_sequence()?));
+            t.child[2] = self.stmt_sequence().map(Box::new);
         }
         self.match_token(Token::End);
-        Ok(t)
+        t.set_span(self.span_from(start));
+        self.finish_node();
+        t
     }
 
     // repeat-smt -> repeat smt-sequence until expr
-    fn repeat_stmt(&mut self) -> Result<TreeNode> {
+    fn repeat_stmt(&mut self) -> TreeNode {
+        self.start_node(SyntaxKind::RepeatStmt);
         let mut t = TreeNode::new_statement_node(StatementKind::RepeatK);
+        let start = self.token_span();
         self.match_token(Token::Repeat);
-        t.child[0] = Some(Box::new(self.stmt_sequence()?));
+        t.child[0] = self.stmt_sequence().map(Box::new);
         self.match_token(Token::Until);
-        t.child[1] = Some(Box::new(self.expr()?));
-        Ok(t)
+        t.child[1] = self.expr().map(Box::new);
+        t.set_span(self.span_from(start));
+        self.finish_node();
+        t
+    }
+
+    // while-stmt -> while exp do stmt-sequence end
+    fn while_stmt(&mut self) -> TreeNode {
+        self.start_node(SyntaxKind::WhileStmt);
+        let mut t = TreeNode::new_statement_node(StatementKind::WhileK);
+        let start = self.token_span();
+        self.match_token(Token::While);
+        t.child[0] = self.expr().map(Box::new);
+        self.match_token(Token::Do);
+        t.child[1] = self.stmt_sequence().map(Box::new);
+        self.match_token(Token::End);
+        t.set_span(self.span_from(start));
+        self.finish_node();
+        t
+    }
+
+    // for-stmt -> for assign-stmt (to | downto) exp [step exp] do stmt-sequence end
+    fn for_stmt(&mut self) -> TreeNode {
+        self.start_node(SyntaxKind::ForStmt);
+        let mut t = TreeNode::new_statement_node(StatementKind::ForK);
+        let start = self.token_span();
+        self.match_token(Token::For);
+        if let Token::Id(_) = self.token_ref() {
+            t.child[0] = Some(Box::new(self.assign_stmt()));
+        } else {
+            self.error_expected("a loop variable");
+        }
+        // the direction is kept as the attribute, like an operator
+        if self.token_ref() == &Token::Downto {
+            t.attr = Attr::Op(Token::Downto);
+            self.advance();
+        } else {
+            t.attr = Attr::Op(Token::To);
+            self.match_token(Token::To);
+        }
+        t.child[1] = self.expr().map(Box::new);
+        if self.token_ref() == &Token::Step {
+            self.advance();
+            t.child[2] = self.expr().map(Box::new);
+        }
+        self.match_token(Token::Do);
+        t.child[3] = self.stmt_sequence().map(Box::new);
+        self.match_token(Token::End);
+        t.set_span(self.span_from(start));
+        self.finish_node();
+        t
     }
 
     // assign_stmt -> id := expr
-    fn assign_stmt(&mut self) -> Result<TreeNode> {
+    fn assign_stmt(&mut self) -> TreeNode {
+        self.start_node(SyntaxKind::AssignStmt);
         let mut t = TreeNode::new_statement_node(AssignK);
-        let token = self.token_ref();
-        if let Token::Id(id) = token {
+        let start = self.token_span();
+        let token = self.token_ref().clone();
+        if let Token::Id(id) = &token {
             t.attr = Attr::Name(id.clone());
         }
-        self.match_token(token.clone());
+        self.match_token(token);
         self.match_token(Token::Assign);
-        t.child[0] = Some(Box::new(self.expr()?));
-        Ok(t)
+        t.child[0] = self.expr().map(Box::new);
+        t.set_span(self.span_from(start));
+        self.finish_node();
+        t
     }
 
     // read_smt = read id
-    fn read_stmt(&mut self) -> Result<TreeNode> {
+    fn read_stmt(&mut self) -> TreeNode {
+        self.start_node(SyntaxKind::ReadStmt);
         let mut t = TreeNode::new_statement_node(ReadK);
+        let start = self.token_span();
         self.match_token(Token::Read);
-        let token = self.get_token().cloned();
-        if let Some(Token::Id(id)) = token {
-            t.attr = Attr::Name(id);
+        if let Token::Id(id) = self.token_ref() {
+            t.attr = Attr::Name(id.clone());
+            self.advance();
+        } else {
+            self.error_expected("a variable name");
         }
-        Ok(t)
+        t.set_span(self.span_from(start));
+        self.finish_node();
+        t
     }
 
     // write_smt = write expr
-    fn write_stmt(&mut self) -> Result<TreeNode> {
+    fn write_stmt(&mut self) -> TreeNode {
+        self.start_node(SyntaxKind::WriteStmt);
         let mut t = TreeNode::new_statement_node(WriteK);
+        let start = self.token_span();
         self.match_token(Token::Write);
-        t.child[0] = Some(Box::new(self.expr()?));
-        Ok(t)
+        t.child[0] = self.expr().map(Box::new);
+        t.set_span(self.span_from(start));
+        self.finish_node();
+        t
+    }
+
+    // expr = and_expr { "or" and_expr }*
+    fn expr(&mut self) -> Option<TreeNode> {
+        let checkpoint = self.checkpoint();
+        let mut t = self.and_expr();
+        while self.token_ref() == &Token::Or {
+            t = Some(self.binary(checkpoint, t, Self::and_expr));
+        }
+        t
     }
 
-    // expr -> simple_exp ["<" simple-exp | "=" simple-exp]
-    fn expr(&mut self) -> Result<TreeNode> {
-        let mut t = self.simple_expr()?;
-        let token = self.token_ref();
-        if token == &Token::Lt || token == &Token::Eq {
-            let mut p = TreeNode::new_expression_node(Opk);
-            p.child[0] = Some(Box::new(t));
-            p.attr = Attr::Op(token.clone());
-            t = p;
-            self.match_token(token.clone());
-            t.child[1] = Some(Box::new(self.simple_expr()?));
+    // and_expr = not_expr { "and" not_expr }*
+    fn and_expr(&mut self) -> Option<TreeNode> {
+        let checkpoint = self.checkpoint();
+        let mut t = self.not_expr();
+        while self.token_ref() == &Token::And {
+            t = Some(self.binary(checkpoint, t, Self::not_expr));
         }
-        Ok(t)
+        t
+    }
+
+    // not_expr = "not" not_expr | comparison
+    fn not_expr(&mut self) -> Option<TreeNode> {
+        if self.token_ref() == &Token::Not {
+            return Some(self.unary(Self::not_expr));
+        }
+        self.comparison()
+    }
+
+    // comparison = simple_expr [ ("<" | "<=" | ">" | ">=" | "=" | "<>") simple_expr ]
+    fn comparison(&mut self) -> Option<TreeNode> {
+        let checkpoint = self.checkpoint();
+        let t = self.simple_expr();
+        if self.token_ref().is_comparison() {
+            return Some(self.binary(checkpoint, t, Self::simple_expr));
+        }
+        t
     }
 
     // simple_expr = term { "+" term | "-" term }*
-    fn simple_expr(&mut self) -> Result<TreeNode> {
-        let mut t = self.term()?;
+    fn simple_expr(&mut self) -> Option<TreeNode> {
+        let checkpoint = self.checkpoint();
+        let mut t = self.term();
         while self.token_ref() == &Token::Plus || self.token_ref() == &Token::Minus {
-            let token = self.token_ref();
-            let mut p = TreeNode::new_expression_node(Opk);
-            p.child[0] = Some(Box::new(t));
-            p.attr = Attr::Op(token.clone());
-            t = p;
-            self.match_token(token.clone());
-            t.child[1] = Some(Box::new(self.term()?));
-        }
-        Ok(t)
-    }
-
-    // term = factor { " * " factor | " / " factor)*
-    fn term(&mut self) -> Result<TreeNode> {
-        let mut t = self.factor()?;
-        while self.token_ref() == &Token::Times || self.token_ref() == &Token::Over {
-            let mut p = TreeNode::new_expression_node(ExpressionKind::Opk);
-            p.child[0] = Some(Box::new(t));
-            let token = self.token_ref();
-            p.attr = Attr::Op(token.clone());
-            t = p;
-            self.match_token(token.clone());
-            t.child[1] = Some(Box::new(self.factor()?));
-        }
-        Ok(t)
-    }
-
-    // factor = NUM | ID | (exp)
-    fn factor(&mut self) -> Result<TreeNode> {
+            t = Some(self.binary(checkpoint, t, Self::term));
+        }
+        t
+    }
+
+    // term = signed_factor { ("*" | "/" | "%") signed_factor }*
+    fn term(&mut self) -> Option<TreeNode> {
+        let checkpoint = self.checkpoint();
+        let mut t = self.signed_factor();
+        while matches!(self.token_ref(), Token::Times | Token::Over | Token::Mod) {
+            t = Some(self.binary(checkpoint, t, Self::signed_factor));
+        }
+        t
+    }
+
+    // signed_factor = ("-" | "+") signed_factor | factor
+    fn signed_factor(&mut self) -> Option<TreeNode> {
+        let next = self.tokens.get(self.cur_idx + 1).map(|t| &t.token);
+        if self.token_ref() == &Token::Minus && matches!(next, Some(Token::Num(_))) {
+            return self.number(true);
+        }
+        if matches!(self.token_ref(), Token::Minus | Token::Plus) {
+            return Some(self.unary(Self::signed_factor));
+        }
+        self.factor()
+    }
+
+    // lhs <current operator> operand, where lhs was parsed from `checkpoint`
+    fn binary(
+        &mut self,
+        checkpoint: usize,
+        lhs: Option<TreeNode>,
+        operand: fn(&mut Self) -> Option<TreeNode>,
+    ) -> TreeNode {
+        self.start_node_at(checkpoint, SyntaxKind::BinaryExpr);
+        let start = lhs.as_ref().map_or(self.token_span(), |t| t.span);
+        let mut p = TreeNode::new_expression_node(Opk);
+        p.attr = Attr::Op(self.token_ref().clone());
+        p.child[0] = lhs.map(Box::new);
+        self.advance();
+        p.child[1] = operand(self).map(Box::new);
+        p.set_span(self.span_from(start));
+        self.finish_node();
+        p
+    }
+
+    // <current operator> operand
+    fn unary(&mut self, operand: fn(&mut Self) -> Option<TreeNode>) -> TreeNode {
+        self.start_node(SyntaxKind::UnaryExpr);
+        let start = self.token_span();
+        let mut p = TreeNode::new_expression_node(Opk);
+        p.attr = Attr::Op(self.token_ref().clone());
+        self.advance();
+        p.child[0] = operand(self).map(Box::new);
+        p.set_span(self.span_from(start));
+        self.finish_node();
+        p
+    }
+
+    // NUM, or "-" NUM when `negative`. The sign is folded into the literal
+    // so that the smallest integer can be written as one.
+    fn number(&mut self, negative: bool) -> Option<TreeNode> {
+        let start = self.token_span();
+        if negative {
+            self.start_node(SyntaxKind::UnaryExpr);
+            self.advance();
+        }
+        let Token::Num(digits) = self.token_ref().clone() else {
+            unreachable!("number() called without a number");
+        };
+        self.start_node(SyntaxKind::Literal);
+        self.advance();
+        self.finish_node();
+        if negative {
+            self.finish_node();
+        }
+        let span = self.span_from(start);
+        let value = digits
+            .parse::<i64>()
+            .ok()
+            .map(|val| if negative { -val } else { val })
+            .and_then(|val| i32::try_from(val).ok());
+        let Some(value) = value else {
+            let sign = if negative { "-" } else { "" };
+            let message = format!("integer literal `{}{}` is too large", sign, digits);
+            self.diagnostics.push(
+                Diagnostic::error("E0103", message, span).with_label("does not fit in 32 bits"),
+            );
+            return None;
+        };
+        let mut t = TreeNode::new_expression_node(ExpressionKind::ConstK);
+        t.attr = Attr::Val(value);
+        t.set_span(span);
+        Some(t)
+    }
+
+    // factor = NUM | true | false | ID | (exp)
+    fn factor(&mut self) -> Option<TreeNode> {
         let mut t: TreeNode;
         let token = self.token_ref().clone();
+        let span = self.token_span();
         match token {
-            Token::Num(ref str) => {
+            Token::Num(_) => return self.number(false),
+            Token::True | Token::False => {
+                self.start_node(SyntaxKind::Literal);
+                self.advance();
+                self.finish_node();
                 t = TreeNode::new_expression_node(ExpressionKind::ConstK);
-                t.attr = Attr::Val(str.parse::<i32>()?);
-                self.match_token(token.clone());
+                t.attr = Attr::Bool(token == Token::True);
+                t.set_span(span);
             }
             Token::Id(ref id) => {
                 t = TreeNode::new_expression_node(ExpressionKind::IdK);
                 t.attr = Attr::Name(id.clone());
-                self.match_token(token.clone());
+                t.set_span(span);
+                self.start_node(SyntaxKind::Name);
+                self.advance();
+                self.finish_node();
             }
             Token::Lparen => {
+                self.start_node(SyntaxKind::ParenExpr);
                 self.match_token(Token::Lparen);
-                t = self.expr()?;
+                let inner = self.expr();
                 self.match_token(Token::Rparen);
+                self.finish_node();
+                return inner;
             }
             _ => {
-                self.get_token();
-                return Err(anyhow::format_err!("{}", token));
+                let message = format!("expected an expression, found {}", token.describe());
+                let diagnostic =
+                    Diagnostic::error("E0102", message, span).with_label("expected an expression");
+                self.error(diagnostic);
+                return None;
             }
         }
-        Ok(t)
+        Some(t)
     }
 
     fn match_token(&mut self, expected: Token) -> bool {
-        let token = self.token_ref();
-        if token.clone() == expected {
-            self.get_token();
+        if self.token_ref() == &expected {
+            self.advance();
             return true;
         }
-        println!("token {}, expected {}", token, expected);
+        self.error_expected(&expected.describe());
         false
     }
 }
 
+// appends `rest` to the end of the sibling chain starting at `seq`
+fn append_sibling(seq: &mut Option<TreeNode>, rest: Option<TreeNode>) {
+    let Some(rest) = rest else {
+        return;
+    };
+    match seq {
+        None => *seq = Some(rest),
+        Some(node) => {
+            let mut p = node;
+            while p.sibling.is_some() {
+                p = p.sibling.as_mut().unwrap();
+            }
+            p.sibling = Some(Box::new(rest));
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
+    use crate::ast::Attr;
+    use crate::diagnostic::Diagnostic;
+    use crate::driver::scan;
     use crate::parser::Parser;
-    use crate::token::Token;
+    use crate::scanner::Scanner;
+    use crate::token::{Span, Token};
     use anyhow::Result;
 
     #[test]
@@ -248,4 +621,140 @@ mod tests {
         println!("{}", node);
         Ok(())
     }
+
+    #[test]
+    fn test_parse_spans() -> Result<()> {
+        let input = "read x;\nif 0 < x then\n  write x * 2\nend";
+        let mut scanner = Scanner::new(input);
+        let mut tokens = vec![];
+        loop {
+            let token = scanner.get_spanned_token();
+            tokens.push(token.clone());
+            if token.token == Token::EndFile {
+                break;
+            }
+        }
+        let node = Parser::new(tokens).parse()?;
+        let text = |span: Span| &input[span.start..span.end];
+        assert_eq!(text(node.span), "read x");
+        assert_eq!(node.line_number, 1);
+
+        let if_node = node.sibling.as_ref().unwrap();
+        assert_eq!(if_node.span, Span::new(8, 39, 2, 1));
+        assert_eq!(text(if_node.child[0].as_ref().unwrap().span), "0 < x");
+        let write_node = if_node.child[1].as_ref().unwrap();
+        assert_eq!(text(write_node.span), "write x * 2");
+        assert_eq!(write_node.line_number, 3);
+        let op = write_node.child[0].as_ref().unwrap();
+        assert_eq!(op.span, Span::new(30, 35, 3, 9));
+        assert_eq!(op.child[1].as_ref().unwrap().span, Span::new(34, 35, 3, 13));
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_error() {
+        let tokens = vec![Token::Write, Token::Then, Token::EndFile];
+        let err = Parser::new(tokens).parse().unwrap_err();
+        let diagnostic = err.downcast_ref::<Diagnostic>().unwrap();
+        assert_eq!(diagnostic.code, "E0102");
+        assert_eq!(diagnostic.message, "expected an expression, found `then`");
+    }
+
+    #[test]
+    fn test_parse_recovering() {
+        let input = "read x;
+if x < then
+  y := 1 +;
+  write y
+end;
+repeat x := x - 1 until x = 0 end;
+write x";
+        let mut parser = Parser::new(scan(input));
+        let (tree, diagnostics) = parser.parse_recovering();
+        let errors: Vec<(&str, usize, &str)> = diagnostics
+            .iter()
+            .map(|d| (d.code, d.span.line, d.message.as_str()))
+            .collect();
+        assert_eq!(
+            errors,
+            vec![
+                ("E0102", 2, "expected an expression, found `then`"),
+                ("E0102", 3, "expected an expression, found `;`"),
+                ("E0100", 6, "expected end of file, found `end`"),
+            ]
+        );
+
+        // read, if, repeat and the final write all made it into the tree
+        let tree = tree.unwrap();
+        let if_node = tree.sibling.as_ref().unwrap();
+        let cond = if_node.child[0].as_ref().unwrap();
+        assert!(cond.child[0].is_some() && cond.child[1].is_none());
+        let assign = if_node.child[1].as_ref().unwrap();
+        assert_eq!(assign.attr, Attr::Name("y".into()));
+        assert!(assign.sibling.is_some());
+        let repeat_node = if_node.sibling.as_ref().unwrap();
+        assert!(repeat_node.sibling.is_some());
+
+        assert!(parser.parse().is_err());
+    }
+
+    #[test]
+    fn test_parse_stray_tokens() {
+        let errors = |input: &str| -> Vec<(&'static str, usize, usize, String)> {
+            let (_, diagnostics) = Parser::new(scan(input)).parse_recovering();
+            diagnostics
+                .into_iter()
+                .map(|d| (d.code, d.span.line, d.span.col, d.message))
+                .collect()
+        };
+        assert_eq!(
+            errors("end end end"),
+            vec![("E0101", 1, 1, "expected a statement, found `end`".into())]
+        );
+        assert_eq!(
+            errors("if a then x:=1 else y:=2 else z:=3 end"),
+            vec![
+                ("E0100", 1, 26, "expected `end`, found `else`".into()),
+                // the final `end` no longer has an `if` to close
+                ("E0100", 1, 36, "expected end of file, found `end`".into()),
+            ]
+        );
+        assert_eq!(
+            errors("x := 1 end; write x"),
+            vec![("E0100", 1, 8, "expected end of file, found `end`".into())]
+        );
+    }
+
+    #[test]
+    fn test_parse_negative_literal() -> Result<()> {
+        let tree = Parser::new(scan("write -2147483648 - -1")).parse()?;
+        let sub = tree.child[0].as_ref().unwrap();
+        assert_eq!(sub.child[0].as_ref().unwrap().attr, Attr::Val(i32::MIN));
+        assert_eq!(sub.child[1].as_ref().unwrap().attr, Attr::Val(-1));
+        assert_eq!(sub.child[0].as_ref().unwrap().span, Span::new(6, 17, 1, 7));
+
+        let err = Parser::new(scan("write -2147483649")).parse().unwrap_err();
+        let diagnostic = err.downcast_ref::<Diagnostic>().unwrap();
+        assert_eq!(diagnostic.code, "E0103");
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_missing_tokens() {
+        let mut parser = Parser::new(scan("x := 1 y := 2; if x then write y"));
+        let (tree, diagnostics) = parser.parse_recovering();
+        let messages: Vec<&str> = diagnostics.iter().map(|d| d.message.as_str()).collect();
+        assert_eq!(
+            messages,
+            vec![
+                "expected `;`, found identifier `y`",
+                "expected `end`, found end of file",
+            ]
+        );
+        assert!(tree.unwrap().sibling.is_some());
+
+        let (tree, diagnostics) = Parser::new(scan("")).parse_recovering();
+        assert!(tree.is_none());
+        assert_eq!(diagnostics[0].code, "E0101");
+    }
 }
@@ -1,9 +1,11 @@
-use crate::token::Token;
+use crate::diagnostic::Diagnostic;
+use crate::token::{Span, SpannedToken, Token, Trivia, TriviaKind};
 use std::str::Chars;
 
-const RESERVED_COUNT: usize = 8;
+const RESERVED_COUNT: usize = 20;
 const KEYWORDS: [&str; RESERVED_COUNT] = [
-    "if", "then", "else", "end", "repeat", "until", "read", "write",
+    "if", "then", "else", "end", "repeat", "until", "while", "do", "for", "to", "downto", "step",
+    "read", "write", "and", "or", "not", "true", "false", "mod",
 ];
 
 const KEY_TYPES: [Token; RESERVED_COUNT] = [
@@ -13,8 +15,20 @@ const KEY_TYPES: [Token; RESERVED_COUNT] = [
     Token::End,
     Token::Repeat,
     Token::Until,
+    Token::While,
+    Token::Do,
+    Token::For,
+    Token::To,
+    Token::Downto,
+    Token::Step,
     Token::Read,
     Token::Write,
+    Token::And,
+    Token::Or,
+    Token::Not,
+    Token::True,
+    Token::False,
+    Token::Mod,
 ];
 
 fn reserved_lookup(str: String) -> Token {
@@ -26,244 +40,281 @@ fn reserved_lookup(str: String) -> Token {
     Token::Id(str)
 }
 
-#[derive(PartialEq, Debug)]
-enum StateType {
-    Start,
-    InAssign,
-    InComment,
-    InNum,
-    InId,
-    Done,
+/// Which characters may follow the first letter of an identifier.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum IdentRule {
+    /// letters only, as in Louden's scanner: `x1` scans as `x` followed by `1`
+    Letters,
+    /// letters and digits
+    #[default]
+    Alphanumeric,
 }
 
+impl IdentRule {
+    fn continues(self, c: char) -> bool {
+        match self {
+            IdentRule::Letters => c.is_ascii_alphabetic(),
+            IdentRule::Alphanumeric => c.is_ascii_alphanumeric(),
+        }
+    }
+}
+
+/// The TINY lexer. Besides `get_token` it can be used as an iterator, which
+/// yields every token up to and including `Token::EndFile`.
 pub struct Scanner<'a> {
-    //input: &'a str,
+    ident_rule: IdentRule,
+    // byte offset of the next character to be consumed
+    offset: usize,
     line_position: usize,
     line_num: usize,
+    // span of the token returned last
+    token_span: Span,
+    diagnostics: Vec<Diagnostic>,
+    // spans of the `{ }` comments skipped so far
+    comments: Vec<Span>,
+    // leading trivia of the token returned last, recorded in lossless mode
+    keep_trivia: bool,
+    trivia: Vec<Trivia>,
     it: Chars<'a>,
     next_char: Option<char>,
+    // set once the iterator has yielded `EndFile`
+    done: bool,
 }
 
 impl<'a> Scanner<'a> {
     pub fn new(input: &'a str) -> Self {
         Self {
-            // input,
+            ident_rule: IdentRule::default(),
+            offset: 0,
             line_position: 0,
-            line_num: 0,
+            line_num: 1,
+            token_span: Span::default(),
+            diagnostics: vec![],
+            comments: vec![],
+            keep_trivia: false,
+            trivia: vec![],
             it: input.chars(),
             next_char: None,
+            done: false,
         }
     }
-    #[allow(unused_assignments)]
+
+    pub fn with_ident_rule(mut self, ident_rule: IdentRule) -> Self {
+        self.ident_rule = ident_rule;
+        self
+    }
+
+    /// Lossless mode: the whitespace and comments skipped before each token
+    /// are kept and can be read back with `trivia`.
+    pub fn with_trivia(mut self) -> Self {
+        self.keep_trivia = true;
+        self
+    }
+
+    /// Scans the next token. Comments and whitespace are skipped, lexical
+    /// errors are recorded in `diagnostics` and scanning goes on after them.
     pub fn get_token(&mut self) -> Token {
-        let mut token_string = String::from("");
-        let mut token = Token::EndFile;
-        let mut state = StateType::Start;
-        let mut save = false;
-        while state != StateType::Done {
-            let c = self.get_peek_char();
-            if c.is_none() {
-                self.get_next_char();
-                break;
-            }
-            save = true;
-            let c = c.unwrap();
-            if c == '\n' {
-                self.line_num += 1;
-                self.line_position = 0;
-            } else {
-                self.line_position += 1;
-            }
-            match state {
-                StateType::Start => {
-                    if c.is_ascii_digit() {
-                        state = StateType::InNum;
-                    } else if c.is_ascii_alphabetic() {
-                        state = StateType::InId;
-                    } else if c == ':' {
-                        state = StateType::InAssign;
-                    } else if c.is_ascii_whitespace() {
-                        save = false;
-                    } else if c == '{' {
-                        save = false;
-                        state = StateType::InComment;
-                    } else {
-                        state = StateType::Done;
-                        match c {
-                            '=' => token = Token::Eq,
-                            '<' => token = Token::Lt,
-                            '+' => token = Token::Plus,
-                            '-' => token = Token::Minus,
-                            '*' => token = Token::Times,
-                            '/' => token = Token::Over,
-                            '(' => token = Token::Lparen,
-                            ')' => token = Token::Rparen,
-                            ';' => token = Token::Semi,
-                            _ => token = Token::Error(token_string.clone()),
-                        }
-                    }
-                    self.get_next_char();
+        let mut start;
+        self.trivia.clear();
+        let token = loop {
+            start = self.here();
+            let Some(c) = self.get_next_char() else {
+                break Token::EndFile;
+            };
+            match c {
+                '{' => {
+                    self.skip_comment(start);
+                    self.push_trivia(TriviaKind::Comment, start);
                 }
-                StateType::InComment => {
-                    save = false;
-                    if c == '}' {
-                        state = StateType::Start;
+                '=' => break Token::Eq,
+                '<' => match self.get_peek_char() {
+                    Some('=') => {
+                        self.get_next_char();
+                        break Token::Le;
                     }
-                    self.get_next_char();
-                }
-                StateType::InAssign => {
-                    state = StateType::Done;
-                    if c == '=' {
-                        token = Token::Assign;
+                    Some('>') => {
                         self.get_next_char();
-                    } else {
-                        save = false;
-                        token = Token::Error(token_string.clone());
+                        break Token::Ne;
                     }
-                }
-                StateType::InNum => {
-                    if !c.is_ascii_digit() {
-                        save = false;
-                        state = StateType::Done;
-                        token = Token::Num(token_string.clone());
-                    } else {
+                    _ => break Token::Lt,
+                },
+                '>' => {
+                    if self.get_peek_char() == Some('=') {
                         self.get_next_char();
+                        break Token::Ge;
                     }
+                    break Token::Gt;
                 }
-                StateType::InId => {
-                    if !c.is_ascii_alphabetic() {
-                        save = false;
-                        state = StateType::Done;
-                        token = reserved_lookup(token_string.clone());
-                    } else {
+                '+' => break Token::Plus,
+                '-' => break Token::Minus,
+                '*' => break Token::Times,
+                '/' => break Token::Over,
+                '%' => break Token::Mod,
+                '(' => break Token::Lparen,
+                ')' => break Token::Rparen,
+                ';' => break Token::Semi,
+                ':' => {
+                    if self.get_peek_char() == Some('=') {
                         self.get_next_char();
+                        break Token::Assign;
                     }
+                    break Token::Error(":".into());
                 }
-                StateType::Done => {}
-            }
-            if save {
-                token_string.push(c);
+                _ if c.is_ascii_whitespace() => self.push_trivia(TriviaKind::Whitespace, start),
+                _ if c.is_ascii_digit() => {
+                    break Token::Num(self.take_while(c, |ch| ch.is_ascii_digit()));
+                }
+                _ if c.is_ascii_alphabetic() => {
+                    let rule = self.ident_rule;
+                    break reserved_lookup(self.take_while(c, |ch| rule.continues(ch)));
+                }
+                _ => break Token::Error(c.to_string()),
             }
+        };
+        self.token_span = self.span_from(start);
+        self.check_token(&token);
+        token
+    }
+
+    // `first` followed by every character accepted by `accept`
+    fn take_while(&mut self, first: char, accept: impl Fn(char) -> bool) -> String {
+        let mut text = first.to_string();
+        while let Some(ch) = self.get_peek_char().filter(|&ch| accept(ch)) {
+            text.push(ch);
+            self.get_next_char();
         }
-        if state == StateType::InNum {
-            token = Token::Num(token_string.clone());
-        } else if state == StateType::InId {
-            token = reserved_lookup(token_string.clone());
+        text
+    }
+
+    // records the trivia from `start` to here, merging runs of whitespace
+    fn push_trivia(&mut self, kind: TriviaKind, start: Span) {
+        if !self.keep_trivia {
+            return;
+        }
+        let span = self.span_from(start);
+        match self.trivia.last_mut() {
+            Some(last)
+                if kind == TriviaKind::Whitespace
+                    && last.kind == kind
+                    && last.span.end == span.start =>
+            {
+                last.span.end = span.end;
+            }
+            _ => self.trivia.push(Trivia { kind, span }),
         }
-        token
     }
 
-    pub fn get_token2(&mut self) -> Token {
-        let mut token = Token::EndFile;
+    // skips to the `}` closing the comment opened at `start`
+    fn skip_comment(&mut self, start: Span) {
         loop {
-            let c = self.get_next_char();
-            if c.is_none() {
-                break;
-            }
-            let c = c.unwrap();
-            if c.is_ascii_whitespace() {
-                continue;
-            }
-            match c {
-                '=' => {
-                    token = Token::Eq;
-                    break;
-                }
-                '<' => {
-                    token = Token::Lt;
-                    break;
-                }
-                '+' => {
-                    token = Token::Plus;
-                    break;
-                }
-                '-' => {
-                    token = Token::Minus;
-                    break;
-                }
-                '*' => {
-                    token = Token::Times;
+            match self.get_next_char() {
+                Some('}') => {
+                    self.comments.push(self.span_from(start));
                     break;
                 }
-                '/' => {
-                    token = Token::Over;
+                Some(_) => {}
+                None => {
+                    self.unterminated_comment(start);
                     break;
                 }
-                '(' => {
-                    token = Token::Lparen;
-                    break;
-                }
-                ')' => {
-                    token = Token::Rparen;
-                    break;
-                }
-                ';' => {
-                    token = Token::Semi;
-                    break;
-                }
-                '{' => {
-                    let mut ch = self.get_next_char();
-                    while ch != Some('}') {
-                        ch = self.get_next_char();
-                    }
-                }
-                ':' => {
-                    let ch = self.get_peek_char();
-                    if ch == Some('=') {
-                        token = Token::Assign;
-                    } else {
-                        // let str = ":" + ch.unwrap_or(')
-                        token = Token::Error(String::from(":"));
-                    }
-                    self.get_next_char();
-                    break;
-                }
-                _ => {
-                    if c.is_ascii_digit() {
-                        let mut num = String::from("");
-                        num.push(c);
-                        loop {
-                            let ch = self.get_peek_char();
-                            if ch.is_none() || !ch.unwrap().is_ascii_digit() {
-                                break;
-                            }
-                            let ch = ch.unwrap();
-                            num.push(ch);
-                            self.get_next_char();
-                        }
-                        token = Token::Num(num);
-                        break;
-                    }
-                    if c.is_ascii_alphabetic() {
-                        let mut id = String::from("");
-                        id.push(c);
-                        loop {
-                            let ch = self.get_peek_char();
-                            if ch.is_none() || !ch.unwrap().is_ascii_alphanumeric() {
-                                break;
-                            }
-                            let ch = ch.unwrap();
-                            id.push(ch);
-                            self.get_next_char();
-                        }
-                        token = reserved_lookup(id);
-                        break;
-                    }
-                }
             }
         }
-        token
+    }
+
+    /// Lexical errors found so far, in source order.
+    pub fn diagnostics(&self) -> &[Diagnostic] {
+        &self.diagnostics
+    }
+
+    /// Whitespace and comments between the token returned last and the one
+    /// before it. Always empty unless the scanner was made `with_trivia`.
+    pub fn trivia(&self) -> &[Trivia] {
+        &self.trivia
+    }
+
+    /// Spans of the comments skipped so far, braces included.
+    pub fn comments(&self) -> &[Span] {
+        &self.comments
+    }
+
+    fn check_token(&mut self, token: &Token) {
+        if let Token::Error(text) = token {
+            let diagnostic = if text == ":" {
+                Diagnostic::error("E0002", "expected `=` after `:`".into(), self.token_span)
+                    .with_label("assignment is written `:=`")
+            } else {
+                let message = format!("unexpected character `{}`", text);
+                Diagnostic::error("E0001", message, self.token_span)
+                    .with_label("not part of the TINY language")
+            };
+            self.diagnostics.push(diagnostic);
+        }
+    }
+
+    // `start` is the position of the opening `{`
+    fn unterminated_comment(&mut self, start: Span) {
+        let span = Span {
+            end: start.start + 1,
+            ..start
+        };
+        let diagnostic = Diagnostic::error("E0003", "unterminated comment".into(), span)
+            .with_label("comment starts here")
+            .with_note("comments run until the next `}`");
+        self.diagnostics.push(diagnostic);
+    }
+
+    /// Scans the next token and pairs it with its span.
+    pub fn get_spanned_token(&mut self) -> SpannedToken {
+        let token = self.get_token();
+        SpannedToken::new(token, self.token_span)
+    }
+
+    /// The span of the token returned by the last `get_token` call.
+    pub fn token_span(&self) -> Span {
+        self.token_span
+    }
+
+    /// The (1-based) source line of the most recently consumed character.
+    pub fn line_number(&self) -> usize {
+        self.line_num
+    }
+
+    // empty span at the next character to be consumed
+    fn here(&self) -> Span {
+        Span::new(
+            self.offset,
+            self.offset,
+            self.line_num,
+            self.line_position + 1,
+        )
+    }
+
+    fn span_from(&self, start: Span) -> Span {
+        Span {
+            end: self.offset,
+            ..start
+        }
     }
 
     fn get_next_char(&mut self) -> Option<char> {
-        match self.next_char {
+        let ch = match self.next_char {
             Some(_) => {
                 let ch = self.next_char;
                 self.next_char = None;
                 ch
             }
             None => self.it.next(),
+        };
+        if let Some(c) = ch {
+            self.offset += c.len_utf8();
+        }
+        if ch == Some('\n') {
+            self.line_num += 1;
+            self.line_position = 0;
+        } else if ch.is_some() {
+            self.line_position += 1;
         }
+        ch
     }
 
     fn get_peek_char(&mut self) -> Option<char> {
@@ -274,13 +325,32 @@ impl<'a> Scanner<'a> {
     }
 }
 
+impl Iterator for Scanner<'_> {
+    type Item = Token;
+
+    fn next(&mut self) -> Option<Token> {
+        if self.done {
+            return None;
+        }
+        let token = self.get_token();
+        self.done = token == Token::EndFile;
+        Some(token)
+    }
+}
+
+/// Scans `input` with the default identifier rule. The tokens end with
+/// `Token::EndFile`, ready for `Parser::new`.
+pub fn tokenize(input: &str) -> Vec<Token> {
+    Scanner::new(input).collect()
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::scanner::Scanner;
-    use crate::token::Token;
+    use crate::scanner::{tokenize, IdentRule, Scanner};
+    use crate::token::{Span, Token};
 
     #[test]
-    fn test_get_token2() {
+    fn test_get_token() {
         let input = "{ Sample program
   in TINY language -
   computes factorial
@@ -333,7 +403,7 @@ end";
             Token::EndFile,
         ];
         loop {
-            let token = scanner.get_token2();
+            let token = scanner.get_token();
             tokens.push(token.clone());
             if token == Token::EndFile {
                 break;
@@ -343,7 +413,7 @@ end";
     }
 
     #[test]
-    fn test_get_token2_1() {
+    fn test_tokenize() {
         let input = "{
   Factorial Program in TINY language
   ----------------------------------
@@ -359,8 +429,6 @@ if 0 < x then   { don't compute if x <= 0 }
   write fact;   { output factorial of x }
 end";
 
-        let mut scanner = Scanner::new(input);
-        let mut tokens = vec![];
         let rets = vec![
             Token::Read,
             Token::Id("x".into()),
@@ -398,13 +466,160 @@ end";
             Token::End,
             Token::EndFile,
         ];
+        assert_eq!(rets, tokenize(input));
+    }
+
+    #[test]
+    fn test_token_span() {
+        let input = "{ comment }\nread x1;\n  y := 10";
+        let mut scanner = Scanner::new(input);
+        let mut spans = vec![];
         loop {
-            let token = scanner.get_token2();
-            tokens.push(token.clone());
-            if token == Token::EndFile {
+            let token = scanner.get_spanned_token();
+            spans.push((token.span, &input[token.span.start..token.span.end]));
+            if token.token == Token::EndFile {
                 break;
             }
         }
-        assert_eq!(rets, tokens);
+        assert_eq!(
+            spans,
+            vec![
+                (Span::new(12, 16, 2, 1), "read"),
+                (Span::new(17, 19, 2, 6), "x1"),
+                (Span::new(19, 20, 2, 8), ";"),
+                (Span::new(23, 24, 3, 3), "y"),
+                (Span::new(25, 27, 3, 5), ":="),
+                (Span::new(28, 30, 3, 8), "10"),
+                (Span::new(30, 30, 3, 10), ""),
+            ]
+        );
+        assert_eq!(scanner.comments(), &[Span::new(0, 11, 1, 1)]);
+
+        let mut scanner = Scanner::new("  x:=y1").with_ident_rule(IdentRule::Letters);
+        assert_eq!(scanner.get_token(), Token::Id("x".into()));
+        assert_eq!(scanner.token_span(), Span::new(2, 3, 1, 3));
+        assert_eq!(scanner.get_token(), Token::Assign);
+        assert_eq!(scanner.token_span(), Span::new(3, 5, 1, 4));
+        assert_eq!(scanner.get_token(), Token::Id("y".into()));
+        assert_eq!(scanner.token_span(), Span::new(5, 6, 1, 6));
+        assert_eq!(scanner.get_token(), Token::Num("1".into()));
+        assert_eq!(scanner.token_span(), Span::new(6, 7, 1, 7));
+    }
+
+    #[test]
+    fn test_diagnostics() {
+        let mut scanner = Scanner::new("x : 1");
+        while scanner.get_token() != Token::EndFile {}
+        let diagnostics = scanner.diagnostics();
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].code, "E0002");
+        assert_eq!(diagnostics[0].span, Span::new(2, 3, 1, 3));
+    }
+
+    #[test]
+    fn test_lexical_errors() {
+        let mut scanner = Scanner::new("x := 1 @ 2;\ny :\n{ never closed");
+        let tokens: Vec<Token> = scanner.by_ref().collect();
+        assert_eq!(
+            tokens,
+            vec![
+                Token::Id("x".into()),
+                Token::Assign,
+                Token::Num("1".into()),
+                Token::Error("@".into()),
+                Token::Num("2".into()),
+                Token::Semi,
+                Token::Id("y".into()),
+                Token::Error(":".into()),
+                Token::EndFile,
+            ]
+        );
+        let errors: Vec<(&str, Span)> = scanner
+            .diagnostics()
+            .iter()
+            .map(|d| (d.code, d.span))
+            .collect();
+        assert_eq!(
+            errors,
+            vec![
+                ("E0001", Span::new(7, 8, 1, 8)),
+                ("E0002", Span::new(14, 15, 2, 3)),
+                ("E0003", Span::new(16, 17, 3, 1)),
+            ]
+        );
+        assert_eq!(scanner.token_span(), Span::new(30, 30, 3, 15));
+        assert_eq!(scanner.next(), None);
+    }
+
+    #[test]
+    fn test_operators() {
+        assert_eq!(
+            tokenize("a<=b<>c>=d>e<f=g not(x)and y or z"),
+            vec![
+                Token::Id("a".into()),
+                Token::Le,
+                Token::Id("b".into()),
+                Token::Ne,
+                Token::Id("c".into()),
+                Token::Ge,
+                Token::Id("d".into()),
+                Token::Gt,
+                Token::Id("e".into()),
+                Token::Lt,
+                Token::Id("f".into()),
+                Token::Eq,
+                Token::Id("g".into()),
+                Token::Not,
+                Token::Lparen,
+                Token::Id("x".into()),
+                Token::Rparen,
+                Token::And,
+                Token::Id("y".into()),
+                Token::Or,
+                Token::Id("z".into()),
+                Token::EndFile,
+            ]
+        );
+        assert_eq!(
+            tokenize("-a%b mod c"),
+            vec![
+                Token::Minus,
+                Token::Id("a".into()),
+                Token::Mod,
+                Token::Id("b".into()),
+                Token::Mod,
+                Token::Id("c".into()),
+                Token::EndFile,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_ident_rule() {
+        let input = "x1 := x2y";
+        assert_eq!(
+            tokenize(input),
+            vec![
+                Token::Id("x1".into()),
+                Token::Assign,
+                Token::Id("x2y".into()),
+                Token::EndFile,
+            ]
+        );
+        let tokens: Vec<Token> = Scanner::new(input)
+            .with_ident_rule(IdentRule::Letters)
+            .collect();
+        assert_eq!(
+            tokens,
+            vec![
+                Token::Id("x".into()),
+                Token::Num("1".into()),
+                Token::Assign,
+                Token::Id("x".into()),
+                Token::Num("2".into()),
+                Token::Id("y".into()),
+                Token::EndFile,
+            ]
+        );
     }
 }
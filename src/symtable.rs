@@ -1,10 +1,15 @@
 use std::collections::{HashMap, LinkedList};
+use std::fmt::{Display, Formatter};
 
 #[derive(Debug, Clone)]
 pub struct SymInfo {
     name: String,
     mem_loc: i32,
     lines: LinkedList<i32>,
+    // lines where the variable is assigned or read into, and where its
+    // value is used
+    defs: Vec<i32>,
+    uses: Vec<i32>,
 }
 
 impl SymInfo {
@@ -13,6 +18,8 @@ impl SymInfo {
             name,
             mem_loc,
             lines,
+            defs: vec![],
+            uses: vec![],
         }
     }
 
@@ -23,6 +30,30 @@ impl SymInfo {
     pub fn get_name(&self) -> &str {
         &self.name
     }
+
+    pub fn get_mem_loc(&self) -> i32 {
+        self.mem_loc
+    }
+
+    /// Every line the variable appears on, in the order they were inserted;
+    /// a line is repeated when the variable occurs on it more than once.
+    pub fn get_lines(&self) -> impl Iterator<Item = i32> + '_ {
+        self.lines.iter().copied()
+    }
+
+    /// The lines where the variable is given a value by `:=` or `read`.
+    pub fn get_defs(&self) -> impl Iterator<Item = i32> + '_ {
+        self.defs.iter().copied()
+    }
+
+    /// The lines where the value of the variable is used in an expression.
+    pub fn get_uses(&self) -> impl Iterator<Item = i32> + '_ {
+        self.uses.iter().copied()
+    }
+
+    pub fn is_used(&self) -> bool {
+        !self.uses.is_empty()
+    }
 }
 
 #[derive(Debug, Clone, Default)]
@@ -52,8 +83,112 @@ impl SymTable {
         }
     }
 
+    /// Records a definition of `name`, which must have been inserted.
+    pub fn st_def(&mut self, name: &str, line_no: i32) {
+        if let Some(info) = self.bucket_list.get_mut(name) {
+            info.defs.push(line_no);
+        }
+    }
+
+    /// Records a use of `name`, which must have been inserted.
+    pub fn st_use(&mut self, name: &str, line_no: i32) {
+        if let Some(info) = self.bucket_list.get_mut(name) {
+            info.uses.push(line_no);
+        }
+    }
+
     pub fn st_lookup(&self, name: &str) -> Option<i32> {
         let sym_info = self.bucket_list.get(name);
         sym_info.map(|info| info.mem_loc)
     }
+
+    pub fn get(&self, name: &str) -> Option<&SymInfo> {
+        self.bucket_list.get(name)
+    }
+
+    pub fn len(&self) -> usize {
+        self.bucket_list.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bucket_list.is_empty()
+    }
+
+    /// The entries ordered by memory location, then name, so the order does
+    /// not depend on the hash map.
+    pub fn iter(&self) -> std::vec::IntoIter<&SymInfo> {
+        let mut infos: Vec<&SymInfo> = self.bucket_list.values().collect();
+        infos.sort_by(|a, b| (a.mem_loc, &a.name).cmp(&(b.mem_loc, &b.name)));
+        infos.into_iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a SymTable {
+    type Item = &'a SymInfo;
+    type IntoIter = std::vec::IntoIter<&'a SymInfo>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+// Louden's printSymTab layout, in `iter` order
+impl Display for SymTable {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Variable Name  Location   Line Numbers")?;
+        writeln!(f, "-------------  --------   ------------")?;
+        for info in self.iter() {
+            write!(f, "{:<14} {:<8}  ", info.name, info.mem_loc)?;
+            for line in info.lines.iter() {
+                write!(f, "{:4} ", line)?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::symtable::SymTable;
+
+    #[test]
+    fn test_sym_table() {
+        let mut sym_table = SymTable::new();
+        assert!(sym_table.is_empty());
+        for (name, line, loc) in [("y", 1, 0), ("x", 2, 1), ("y", 2, 0), ("y", 4, 0)] {
+            if sym_table.st_lookup(name).is_none() {
+                sym_table.st_insert(name, line, loc);
+            } else {
+                sym_table.st_insert(name, line, 0);
+            }
+        }
+        assert_eq!(sym_table.len(), 2);
+        let entries: Vec<(&str, i32, Vec<i32>)> = sym_table
+            .iter()
+            .map(|info| {
+                (
+                    info.get_name(),
+                    info.get_mem_loc(),
+                    info.get_lines().collect(),
+                )
+            })
+            .collect();
+        assert_eq!(entries, vec![("y", 0, vec![1, 2, 4]), ("x", 1, vec![2])]);
+        assert_eq!(sym_table.get("x").map(|info| info.get_mem_loc()), Some(1));
+        sym_table.st_def("y", 1);
+        sym_table.st_use("y", 2);
+        sym_table.st_def("y", 4);
+        let y = sym_table.get("y").unwrap();
+        assert_eq!(y.get_defs().collect::<Vec<_>>(), vec![1, 4]);
+        assert_eq!(y.get_uses().collect::<Vec<_>>(), vec![2]);
+        assert!(y.is_used());
+        assert!(!sym_table.get("x").unwrap().is_used());
+        assert_eq!(
+            sym_table.to_string(),
+            "Variable Name  Location   Line Numbers
+-------------  --------   ------------
+y              0            1    2    4 \nx              1            2 \n"
+        );
+    }
 }
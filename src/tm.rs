@@ -0,0 +1,439 @@
+use anyhow::Result;
+use std::fmt::{Display, Formatter};
+use std::io::{BufRead, Write};
+
+pub const IADDR_SIZE: usize = 1024;
+pub const DADDR_SIZE: usize = 1024;
+pub const NO_REGS: usize = 8;
+pub const PC_REG: usize = 7;
+
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum OpCode {
+    // RR instructions: op r,s,t
+    Halt,
+    In,
+    Out,
+    Add,
+    Sub,
+    Mul,
+    Div,
+    // RM instructions: op r,d(s) with d(s) a data memory address
+    Ld,
+    St,
+    // RA instructions: op r,d(s) with d(s) used as a value
+    Lda,
+    Ldc,
+    Jlt,
+    Jle,
+    Jgt,
+    Jge,
+    Jeq,
+    Jne,
+}
+
+const OP_CODES: [(&str, OpCode); 17] = [
+    ("HALT", OpCode::Halt),
+    ("IN", OpCode::In),
+    ("OUT", OpCode::Out),
+    ("ADD", OpCode::Add),
+    ("SUB", OpCode::Sub),
+    ("MUL", OpCode::Mul),
+    ("DIV", OpCode::Div),
+    ("LD", OpCode::Ld),
+    ("ST", OpCode::St),
+    ("LDA", OpCode::Lda),
+    ("LDC", OpCode::Ldc),
+    ("JLT", OpCode::Jlt),
+    ("JLE", OpCode::Jle),
+    ("JGT", OpCode::Jgt),
+    ("JGE", OpCode::Jge),
+    ("JEQ", OpCode::Jeq),
+    ("JNE", OpCode::Jne),
+];
+
+impl OpCode {
+    fn lookup(word: &str) -> Option<OpCode> {
+        OP_CODES
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(word))
+            .map(|(_, op)| *op)
+    }
+
+    fn is_register_only(&self) -> bool {
+        matches!(
+            self,
+            OpCode::Halt
+                | OpCode::In
+                | OpCode::Out
+                | OpCode::Add
+                | OpCode::Sub
+                | OpCode::Mul
+                | OpCode::Div
+        )
+    }
+}
+
+impl Display for OpCode {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let (name, _) = OP_CODES.iter().find(|(_, op)| op == self).unwrap();
+        f.pad(name)
+    }
+}
+
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub struct Instruction {
+    pub op: OpCode,
+    pub arg1: i32,
+    pub arg2: i32,
+    pub arg3: i32,
+}
+
+impl Instruction {
+    const HALT: Instruction = Instruction {
+        op: OpCode::Halt,
+        arg1: 0,
+        arg2: 0,
+        arg3: 0,
+    };
+}
+
+impl Display for Instruction {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        if self.op.is_register_only() {
+            write!(
+                f,
+                "{:>5}  {},{},{}",
+                self.op, self.arg1, self.arg2, self.arg3
+            )
+        } else {
+            write!(
+                f,
+                "{:>5}  {},{}({})",
+                self.op, self.arg1, self.arg2, self.arg3
+            )
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum StepResult {
+    Okay,
+    Halt,
+}
+
+/// The Tiny Machine from Louden's book: eight registers, an instruction
+/// memory and a data memory, with register 7 as the program counter.
+pub struct Machine {
+    imem: Vec<Instruction>,
+    dmem: Vec<i32>,
+    reg: [i32; NO_REGS],
+}
+
+impl Default for Machine {
+    fn default() -> Self {
+        Self::new(IADDR_SIZE, DADDR_SIZE)
+    }
+}
+
+impl Machine {
+    pub fn new(iaddr_size: usize, daddr_size: usize) -> Self {
+        let mut machine = Self {
+            imem: vec![Instruction::HALT; iaddr_size],
+            dmem: vec![0; daddr_size],
+            reg: [0; NO_REGS],
+        };
+        machine.reset();
+        machine
+    }
+
+    /// Clears registers and data memory; location 0 holds the highest data address.
+    pub fn reset(&mut self) {
+        self.reg = [0; NO_REGS];
+        self.dmem.iter_mut().for_each(|m| *m = 0);
+        let max_address = self.dmem.len() as i32 - 1;
+        if let Some(m) = self.dmem.first_mut() {
+            *m = max_address;
+        }
+    }
+
+    pub fn register(&self, r: usize) -> i32 {
+        self.reg[r]
+    }
+
+    pub fn data(&self, addr: usize) -> Option<i32> {
+        self.dmem.get(addr).copied()
+    }
+
+    pub fn instruction(&self, loc: usize) -> Option<&Instruction> {
+        self.imem.get(loc)
+    }
+
+    /// Loads a TM assembly listing such as the one produced by the code
+    /// generator. Blank lines and lines starting with `*` are comments, and
+    /// anything after the operands of an instruction is ignored.
+    pub fn load(&mut self, listing: &str) -> Result<()> {
+        self.imem.iter_mut().for_each(|i| *i = Instruction::HALT);
+        for (idx, line) in listing.lines().enumerate() {
+            let line_no = idx + 1;
+            let line = line.trim_start();
+            if line.is_empty() || line.starts_with('*') {
+                continue;
+            }
+            let (loc, instruction) = parse_instruction(line)
+                .map_err(|msg| anyhow::format_err!("line {}: {}", line_no, msg))?;
+            match self.imem.get_mut(loc) {
+                Some(slot) => *slot = instruction,
+                None => {
+                    return Err(anyhow::format_err!(
+                        "line {}: Location too large: {}",
+                        line_no,
+                        loc
+                    ))
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Executes one instruction, reading `IN` values from `input` and
+    /// writing `OUT` values to `output`.
+    pub fn step(&mut self, input: &mut dyn BufRead, output: &mut dyn Write) -> Result<StepResult> {
+        let pc = self.reg[PC_REG];
+        let instruction = usize::try_from(pc)
+            .ok()
+            .and_then(|pc| self.imem.get(pc))
+            .copied()
+            .ok_or_else(|| anyhow::format_err!("Instruction Memory Fault at {}", pc))?;
+        self.reg[PC_REG] = pc + 1;
+
+        let Instruction {
+            op,
+            arg1: r,
+            arg2,
+            arg3,
+        } = instruction;
+        let r = r as usize;
+        // s and t are registers for RR instructions, m is the effective address otherwise
+        let (s, t) = (arg2 as usize, arg3 as usize);
+        let m = arg2.wrapping_add(self.reg[arg3 as usize]);
+
+        match op {
+            OpCode::Halt => return Ok(StepResult::Halt),
+            OpCode::In => self.reg[r] = read_int(input)?,
+            OpCode::Out => writeln!(output, "{}", self.reg[r])?,
+            OpCode::Add => self.reg[r] = self.reg[s].wrapping_add(self.reg[t]),
+            OpCode::Sub => self.reg[r] = self.reg[s].wrapping_sub(self.reg[t]),
+            OpCode::Mul => self.reg[r] = self.reg[s].wrapping_mul(self.reg[t]),
+            OpCode::Div => {
+                if self.reg[t] == 0 {
+                    return Err(anyhow::format_err!("Division by 0 at {}", pc));
+                }
+                self.reg[r] = self.reg[s].wrapping_div(self.reg[t]);
+            }
+            OpCode::Ld => self.reg[r] = *self.dmem_slot(m, pc)?,
+            OpCode::St => *self.dmem_slot(m, pc)? = self.reg[r],
+            OpCode::Lda => self.reg[r] = m,
+            OpCode::Ldc => self.reg[r] = arg2,
+            OpCode::Jlt | OpCode::Jle | OpCode::Jgt | OpCode::Jge | OpCode::Jeq | OpCode::Jne => {
+                let val = self.reg[r];
+                let taken = match op {
+                    OpCode::Jlt => val < 0,
+                    OpCode::Jle => val <= 0,
+                    OpCode::Jgt => val > 0,
+                    OpCode::Jge => val >= 0,
+                    OpCode::Jeq => val == 0,
+                    _ => val != 0,
+                };
+                if taken {
+                    self.reg[PC_REG] = m;
+                }
+            }
+        }
+        Ok(StepResult::Okay)
+    }
+
+    /// Runs from location 0 until `HALT`, returning the number of executed instructions.
+    pub fn run(&mut self, input: &mut dyn BufRead, output: &mut dyn Write) -> Result<usize> {
+        self.reset();
+        let mut count = 0;
+        loop {
+            count += 1;
+            if self.step(input, output)? == StepResult::Halt {
+                return Ok(count);
+            }
+        }
+    }
+
+    fn dmem_slot(&mut self, addr: i32, pc: i32) -> Result<&mut i32> {
+        usize::try_from(addr)
+            .ok()
+            .and_then(|addr| self.dmem.get_mut(addr))
+            .ok_or_else(|| anyhow::format_err!("Data Memory Fault at {}: address {}", pc, addr))
+    }
+}
+
+pub(crate) fn read_int(input: &mut dyn BufRead) -> Result<i32> {
+    let mut line = String::new();
+    if input.read_line(&mut line)? == 0 {
+        return Err(anyhow::format_err!("no input left for IN instruction"));
+    }
+    line.trim()
+        .parse::<i32>()
+        .map_err(|_| anyhow::format_err!("Illegal value for IN instruction: {}", line.trim()))
+}
+
+// loc:  OP  r,s,t   |   loc:  OP  r,d(s)
+fn parse_instruction(line: &str) -> std::result::Result<(usize, Instruction), String> {
+    let (loc, rest) = line.split_once(':').ok_or("Missing colon")?;
+    let loc = loc
+        .trim()
+        .parse::<usize>()
+        .map_err(|_| "Bad location".to_string())?;
+    let rest = rest.trim_start();
+    let word_end = rest
+        .find(|c: char| !c.is_ascii_alphabetic())
+        .unwrap_or(rest.len());
+    let (word, rest) = rest.split_at(word_end);
+    if word.is_empty() {
+        return Err("Missing opcode".into());
+    }
+    let op = OpCode::lookup(word).ok_or(format!("Illegal opcode: {}", word))?;
+
+    let mut args = Args { rest };
+    let (arg1, arg2, arg3) = if op.is_register_only() {
+        let r = args.register("first")?;
+        args.skip(',')?;
+        let s = args.register("second")?;
+        args.skip(',')?;
+        let t = args.register("third")?;
+        (r, s, t)
+    } else {
+        let r = args.register("first")?;
+        args.skip(',')?;
+        let d = args.num().ok_or("Bad displacement")?;
+        args.skip('(')?;
+        let s = args.register("second")?;
+        args.skip(')')?;
+        (r, d, s)
+    };
+    Ok((
+        loc,
+        Instruction {
+            op,
+            arg1,
+            arg2,
+            arg3,
+        },
+    ))
+}
+
+struct Args<'a> {
+    rest: &'a str,
+}
+
+impl<'a> Args<'a> {
+    fn num(&mut self) -> Option<i32> {
+        let s = self.rest.trim_start();
+        let sign_len = if s.starts_with(['-', '+']) { 1 } else { 0 };
+        let end = s[sign_len..]
+            .find(|c: char| !c.is_ascii_digit())
+            .map_or(s.len(), |i| i + sign_len);
+        let num = s[..end].parse().ok()?;
+        self.rest = &s[end..];
+        Some(num)
+    }
+
+    fn register(&mut self, which: &str) -> std::result::Result<i32, String> {
+        match self.num() {
+            Some(r) if (0..NO_REGS as i32).contains(&r) => Ok(r),
+            _ => Err(format!("Bad {} register", which)),
+        }
+    }
+
+    fn skip(&mut self, ch: char) -> std::result::Result<(), String> {
+        let s = self.rest.trim_start();
+        match s.strip_prefix(ch) {
+            Some(rest) => {
+                self.rest = rest;
+                Ok(())
+            }
+            None => Err(format!("Missing '{}'", ch)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::driver::compile;
+    use crate::tm::{Instruction, Machine, OpCode};
+    use anyhow::Result;
+
+    #[test]
+    fn test_load() -> Result<()> {
+        let mut machine = Machine::default();
+        machine.load(
+            "* comment
+  0:     LD  6,0(0) \tload maxaddress from location 0
+  2:    SUB  0,1,2
+  1:    JEQ  0,-2(7)",
+        )?;
+        assert_eq!(
+            machine.instruction(1),
+            Some(&Instruction {
+                op: OpCode::Jeq,
+                arg1: 0,
+                arg2: -2,
+                arg3: 7
+            })
+        );
+        assert_eq!(
+            format!("{}", machine.instruction(2).unwrap()),
+            "  SUB  0,1,2"
+        );
+        assert_eq!(machine.instruction(3).unwrap().op, OpCode::Halt);
+
+        assert!(machine.load("  0:    FOO  0,0,0").is_err());
+        assert!(machine.load("  0:    ADD  0,9,0").is_err());
+        assert!(machine.load("  5000:    HALT  0,0,0").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_run_factorial() -> Result<()> {
+        let compilation = compile(
+            "read x;
+if 0 < x then
+  fact := 1;
+  repeat
+    fact := fact * x;
+    x := x - 1
+  until x = 0;
+  write fact
+end",
+        )?;
+        let mut machine = Machine::new(1024, 64);
+        machine.load(&compilation.code)?;
+        let mut output = vec![];
+        machine.run(&mut "5\n".as_bytes(), &mut output)?;
+        assert_eq!(String::from_utf8(output)?, "120\n");
+        assert_eq!(machine.data(0), Some(0));
+        assert_eq!(machine.data(1), Some(120));
+        Ok(())
+    }
+
+    #[test]
+    fn test_run_errors() -> Result<()> {
+        let compilation = compile("read x; write 1 / x")?;
+        let mut machine = Machine::default();
+        machine.load(&compilation.code)?;
+        let err = machine.run(&mut "0\n".as_bytes(), &mut vec![]).unwrap_err();
+        assert!(err.to_string().starts_with("Division by 0"));
+        assert!(machine.run(&mut "".as_bytes(), &mut vec![]).is_err());
+
+        let mut machine = Machine::new(8, 8);
+        machine.load("  0:     LD  0,100(0)")?;
+        let err = machine.run(&mut "".as_bytes(), &mut vec![]).unwrap_err();
+        assert!(err.to_string().starts_with("Data Memory Fault"));
+        Ok(())
+    }
+}
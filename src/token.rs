@@ -14,8 +14,19 @@ pub enum Token {
     End,
     Repeat,
     Until,
+    While,
+    Do,
+    For,
+    To,
+    Downto,
+    Step,
     Read,
     Write,
+    And,
+    Or,
+    Not,
+    True,
+    False,
     // multicharacter tokens
     Id(String),
     Num(String),
@@ -24,10 +35,16 @@ pub enum Token {
     Assign,
     Eq,
     Lt,
+    Le,
+    Gt,
+    Ge,
+    Ne,
     Plus,
     Minus,
     Times,
     Over,
+    // `%`, also spelled `mod`
+    Mod,
     Lparen,
     Rparen,
     Semi,
@@ -42,12 +59,27 @@ impl fmt::Display for Token {
             Token::End => write!(f, "reserved word: end"),
             Token::Repeat => write!(f, "reserved word: repeat"),
             Token::Until => write!(f, "reserved word: until"),
+            Token::While => write!(f, "reserved word: while"),
+            Token::Do => write!(f, "reserved word: do"),
+            Token::For => write!(f, "reserved word: for"),
+            Token::To => write!(f, "reserved word: to"),
+            Token::Downto => write!(f, "reserved word: downto"),
+            Token::Step => write!(f, "reserved word: step"),
             Token::Read => write!(f, "reserved word: read"),
             Token::Write => write!(f, "reserved word: write"),
+            Token::And => write!(f, "reserved word: and"),
+            Token::Or => write!(f, "reserved word: or"),
+            Token::Not => write!(f, "reserved word: not"),
+            Token::True => write!(f, "reserved word: true"),
+            Token::False => write!(f, "reserved word: false"),
 
             Token::Assign => write!(f, ":="),
             Token::Lt => write!(f, "<"),
             Token::Eq => write!(f, "="),
+            Token::Le => write!(f, "<="),
+            Token::Gt => write!(f, ">"),
+            Token::Ge => write!(f, ">="),
+            Token::Ne => write!(f, "<>"),
             Token::Lparen => write!(f, "("),
             Token::Rparen => write!(f, ")"),
             Token::Semi => write!(f, ";"),
@@ -55,6 +87,7 @@ impl fmt::Display for Token {
             Token::Minus => write!(f, "-"),
             Token::Times => write!(f, "*"),
             Token::Over => write!(f, "/"),
+            Token::Mod => write!(f, "%"),
             Token::EndFile => write!(f, "EOF"),
             Token::Num(num) => write!(f, "NUM, val= {}", num),
             Token::Id(id) => write!(f, "ID, name= {}", id),
@@ -63,4 +96,108 @@ impl fmt::Display for Token {
     }
 }
 
+impl Token {
+    /// How the token is referred to in diagnostics, e.g. "`then`" or "identifier `x`".
+    pub fn describe(&self) -> String {
+        match &self {
+            Token::EndFile => "end of file".into(),
+            Token::Id(id) => format!("identifier `{}`", id),
+            Token::Num(num) => format!("number `{}`", num),
+            Token::Error(msg) => format!("invalid token `{}`", msg),
+            token => format!("`{}`", token.text()),
+        }
+    }
+
+    /// The token as written in the source, e.g. `<=`, `and` or `x`.
+    pub fn text(&self) -> String {
+        match &self {
+            Token::EndFile => String::new(),
+            Token::Id(text) | Token::Num(text) | Token::Error(text) => text.clone(),
+            token => token
+                .to_string()
+                .trim_start_matches("reserved word: ")
+                .to_string(),
+        }
+    }
+
+    /// Whether the token is one of the comparison operators `<`, `<=`, `>`,
+    /// `>=`, `=` and `<>`.
+    pub fn is_comparison(&self) -> bool {
+        matches!(
+            self,
+            Token::Lt | Token::Le | Token::Gt | Token::Ge | Token::Eq | Token::Ne
+        )
+    }
+}
+
 impl Error for Token {}
+
+/// A region of the source text: the byte offsets `start..end`, plus the
+/// 1-based line and column where it starts.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+    pub line: usize,
+    pub col: usize,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize, line: usize, col: usize) -> Self {
+        Self {
+            start,
+            end,
+            line,
+            col,
+        }
+    }
+
+    /// The smallest span covering both `self` and `other`.
+    pub fn to(&self, other: Span) -> Span {
+        if other.start < self.start {
+            return other.to(*self);
+        }
+        Span {
+            end: self.end.max(other.end),
+            ..*self
+        }
+    }
+}
+
+impl fmt::Display for Span {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.line, self.col)
+    }
+}
+
+/// A token together with the source text it was scanned from.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SpannedToken {
+    pub token: Token,
+    pub span: Span,
+}
+
+impl SpannedToken {
+    pub fn new(token: Token, span: Span) -> Self {
+        Self { token, span }
+    }
+}
+
+impl From<Token> for SpannedToken {
+    fn from(token: Token) -> Self {
+        Self::new(token, Span::default())
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TriviaKind {
+    Whitespace,
+    Comment,
+}
+
+/// Source text between tokens: a run of whitespace or one `{ }` comment.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Trivia {
+    pub kind: TriviaKind,
+    pub span: Span,
+}